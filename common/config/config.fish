@@ -1,5 +1,6 @@
 fenv source ~/.profile
 source ~/.config/alias/main
+test -f ~/.config/owl/owl-start.fish; and source ~/.config/owl/owl-start.fish
 
 set -g -x fish_greeting 'Greetings Tylord. How may I be of service?'
 
@@ -1,24 +0,0 @@
-alias sc='sudo systemctl'
-alias sce='sudo systemctl enable'
-alias scd='sudo systemctl disable'
-alias scr='sudo systemctl restart'
-alias scs='sudo systemctl start'
-alias scx='sudo systemctl stop'
-alias sct='sudo systemctl status'
-alias scl='sudo systemctl list-units'
-alias sca='sudo systemctl list-unit-files'
-alias scdr='sudo systemctl daemon-reload'
-alias su='systemctl --user'
-alias sue='systemctl --user enable'
-alias sud='systemctl --user disable'
-alias sur='systemctl --user restart'
-alias sus='systemctl --user start'
-alias sux='systemctl --user stop'
-alias sut='systemctl --user status'
-alias sul='systemctl --user list-units'
-alias sua='systemctl --user list-unit-files'
-alias sudr='systemctl --user daemon-reload'
-
-
-
-
@@ -11,9 +11,11 @@ export XDG_STATE_HOME="$HOME/.local/state"
 # Local bin
 export PATH="$HOME/.local/bin:$PATH"
 
-# Run owl-rc (all rc scripts)
-if [ -d ~/.config/owl/rc ]; then
-    for file in ~/.config/owl/rc/*; do
-        [ -f "$file" ] && source "$file"
-    done
-fi
\ No newline at end of file
+# Run the loader owl generated for this shell (environment, rc scripts, aliases)
+if [ -n "$ZSH_VERSION" ]; then
+    owl_loader=~/.config/owl/owl-start.zsh
+else
+    owl_loader=~/.config/owl/owl-start.bash
+fi
+[ -f "$owl_loader" ] && source "$owl_loader"
+unset owl_loader
\ No newline at end of file
@@ -38,7 +38,8 @@ return {
                     'texlab',
                     'ltex',
                     'pyright',
-                    'clangd'
+                    'clangd',
+                    'jsonls'
                 }
             })
 
@@ -127,6 +128,12 @@ return {
             })
             vim.lsp.enable('pyright')
 
+            -- JSON (owl setup.json files reference their schema with `$schema`)
+            vim.lsp.config('jsonls', {
+                capabilities = lsp_capabilities,
+            })
+            vim.lsp.enable('jsonls')
+
             do
                 local ok_lsp, lsp = pcall(require, "lspconfig")
                 if ok_lsp and lsp.eslint and type(lsp.eslint.setup) == "function" then
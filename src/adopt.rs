@@ -0,0 +1,194 @@
+use colored::Colorize;
+use serde_json::{json, Value};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::{
+    get_config, get_state, load_setup_by_name, save_state, setup_file, tilde_expand_path, Linkable,
+};
+
+// =======================================
+//            Adopt
+// =======================================
+//
+// `owl adopt <path> --setup <name>` brings a live file under owl: the file
+// moves into the setup's directory, the setup file gains a `local:` link entry
+// for it, and the link is created right away the same way
+// `owl setup <name> link` would.
+
+/// `path` as written in a setup file: `~/...` under the home directory.
+fn target_token(path: &Path) -> String {
+    match dirs::home_dir().and_then(|home| path.strip_prefix(home).ok().map(Path::to_path_buf)) {
+        Some(relative) => format!("~/{}", relative.display()),
+        None => path.display().to_string(),
+    }
+}
+
+fn is_in_home(path: &Path) -> bool {
+    dirs::home_dir().is_some_and(|home| path.starts_with(home))
+}
+
+/// Copy a file or directory tree, keeping permissions and symlinks.
+fn copy_all(from: &Path, to: &Path) -> std::io::Result<()> {
+    let metadata = std::fs::symlink_metadata(from)?;
+    if metadata.file_type().is_symlink() {
+        std::os::unix::fs::symlink(std::fs::read_link(from)?, to)
+    } else if metadata.is_dir() {
+        std::fs::create_dir(to)?;
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            copy_all(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        std::fs::set_permissions(to, metadata.permissions())
+    } else {
+        std::fs::copy(from, to).map(|_| ())
+    }
+}
+
+fn remove_all(path: &Path) -> std::io::Result<()> {
+    if std::fs::symlink_metadata(path)?.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    }
+}
+
+/// `rename`, or a copy and remove when `$HOME` and the repo are on different
+/// filesystems.
+fn rename_or_copy(from: &Path, to: &Path) -> std::io::Result<()> {
+    match std::fs::rename(from, to) {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            if let Err(e) = copy_all(from, to) {
+                let _ = remove_all(to);
+                return Err(e);
+            }
+            remove_all(from)
+        }
+        result => result,
+    }
+}
+
+/// Move `from` to `to`, with sudo for `root`. Files moved with sudo are
+/// handed to `owner`'s owner, so the repo stays editable without root.
+fn move_file(from: &Path, to: &Path, root: bool, owner: Option<&Path>) -> Result<(), String> {
+    if !root {
+        return rename_or_copy(from, to)
+            .map_err(|e| format!("move {} to {}: {}", from.display(), to.display(), e));
+    }
+    let sudo = |args: &[&std::ffi::OsStr]| -> Result<(), String> {
+        let output = Command::new("sudo")
+            .args(args)
+            .output()
+            .map_err(|e| format!("exec sudo: {}", e))?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(format!(
+                "sudo {} failed: {}",
+                args[0].to_string_lossy(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+        }
+    };
+    sudo(&["mv".as_ref(), from.as_os_str(), to.as_os_str()])?;
+    if let Some(owner) = owner {
+        let metadata = std::fs::metadata(owner).map_err(|e| e.to_string())?;
+        let ids = format!("{}:{}", metadata.uid(), metadata.gid());
+        sudo(&[
+            "chown".as_ref(),
+            "-R".as_ref(),
+            ids.as_ref(),
+            to.as_os_str(),
+        ])?;
+    }
+    Ok(())
+}
+
+pub fn adopt(path: &str, setup_name: &str, root: bool) -> Result<(), String> {
+    let owl_path = get_config().owl_path;
+    let mut target = tilde_expand_path(path);
+    if target.is_relative() {
+        let cwd = std::env::current_dir().map_err(|e| e.to_string())?;
+        target = cwd.join(target);
+    }
+
+    let metadata =
+        std::fs::symlink_metadata(&target).map_err(|e| format!("{}: {}", target.display(), e))?;
+    if metadata.file_type().is_symlink() {
+        return Err(format!("{} is already a symlink", target.display()));
+    }
+    if target.starts_with(&owl_path) {
+        return Err(format!("{} is already in the owl repo", target.display()));
+    }
+    if !root && !is_in_home(&target) {
+        return Err(format!(
+            "{} is outside your home directory; pass --root to move and link it with sudo",
+            target.display()
+        ));
+    }
+
+    let setup = load_setup_by_name(setup_name).map_err(|e| {
+        format!(
+            "{} (create the setup with `owl setup new {}`)",
+            e, setup_name
+        )
+    })?;
+    if setup.links.iter().any(|l| l.target_path() == target) {
+        return Err(format!("{} already links {}", setup.name, target.display()));
+    }
+    let file_name = target
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| format!("invalid file name: {}", target.display()))?;
+    let source: PathBuf = setup.origin_dir.join(file_name);
+    if source.exists() {
+        return Err(format!("{} already exists", source.display()));
+    }
+
+    let mut entry = json!({
+        "source": format!("local:{}", file_name),
+        "target": target_token(&target),
+    });
+    if root {
+        entry["root"] = Value::Bool(true);
+    }
+
+    println!(
+        "{} {} ({})",
+        "📥 Adopt".magenta().bold(),
+        target.display().to_string().green(),
+        setup.name.cyan().bold()
+    );
+    move_file(&target, &source, root, Some(&setup.origin_dir))?;
+    println!("  {} {}", "Moved to".green(), source.display());
+    if let Err(e) = setup_file::append_entry(&setup.file, "links", &entry) {
+        // Put the file back so the system is as it was
+        return Err(match move_file(&source, &target, root, None) {
+            Ok(()) => e,
+            Err(back) => format!(
+                "{}; the file is still at {} ({})",
+                e,
+                source.display(),
+                back
+            ),
+        });
+    }
+    println!("  {} {}", "+ link in".green(), setup.file.display());
+
+    let setup = load_setup_by_name(setup_name).map_err(|e| e.to_string())?;
+    let Some(index) = setup.links.iter().position(|l| l.target_path() == target) else {
+        return Err(format!(
+            "the new link is not active here; run `owl setup {} link`",
+            setup_name
+        ));
+    };
+    let mut state = get_state();
+    let errors = setup.run_linkables(&setup.links[index..=index], &mut state);
+    save_state(&state);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("; "))
+    }
+}
@@ -0,0 +1,326 @@
+use colored::Colorize;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::shell::{fish_literal, sh_literal, write_generated, Shell, GENERATED_HEADER};
+use crate::{print_subsection, tilde_expand_path, Setup, SetupShellCommandRaw};
+
+// =======================================
+//            Aliases & Functions
+// =======================================
+//
+// `aliases` and `functions` are written once in setup.json and compiled for
+// bash/zsh, fish and nushell under ~/.config/owl/aliases/.
+//
+// A function body is one simple command per line. Its arguments are written
+// unquoted as `$1`..`$9` or `$@` and translated for each shell; in nushell,
+// `$NAME` also becomes `$env.NAME`. Anything beyond that (pipes into shell
+// specific syntax, `$(...)`, ...) should be limited with `shells`.
+
+fn get_owl_aliases_path() -> PathBuf {
+    tilde_expand_path("~/.config/owl/aliases")
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CommandKind {
+    Alias,
+    Function,
+}
+
+impl CommandKind {
+    fn label(&self) -> &'static str {
+        match self {
+            CommandKind::Alias => "alias",
+            CommandKind::Function => "function",
+        }
+    }
+}
+
+pub struct ShellCommand {
+    pub name: String,
+    pub command: String,
+    pub shells: Vec<Shell>,
+}
+
+impl ShellCommand {
+    fn make(name: &str, raw: &SetupShellCommandRaw, kind: CommandKind) -> Result<Self, String> {
+        let valid_name = match kind {
+            // Aliases like `gc!` or `git-svn-dcommit-push` are common
+            CommandKind::Alias => {
+                !name.is_empty()
+                    && !name
+                        .chars()
+                        .any(|c| c.is_whitespace() || "'\"=/$`\\;|&()<>".contains(c))
+            }
+            CommandKind::Function => {
+                name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            }
+        };
+        if !valid_name {
+            return Err(format!("invalid {} name: {}", kind.label(), name));
+        }
+        let (command, shells) = match raw {
+            SetupShellCommandRaw::Simple(command) => (command, None),
+            SetupShellCommandRaw::Detailed { command, shells } => (command, shells.as_ref()),
+        };
+        if command.trim().is_empty() {
+            return Err(format!("{} {} has an empty command", kind.label(), name));
+        }
+        if kind == CommandKind::Alias && command.contains('\n') {
+            return Err(format!(
+                "alias {} spans several lines; use `functions` instead",
+                name
+            ));
+        }
+        let shells = match shells {
+            Some(names) => names
+                .iter()
+                .map(|s| Shell::from_name(s))
+                .collect::<Result<Vec<_>, _>>()?,
+            None => Shell::ALL.to_vec(),
+        };
+        Ok(Self {
+            name: name.to_string(),
+            command: command.clone(),
+            shells,
+        })
+    }
+
+    pub fn make_all(
+        raw: Option<&BTreeMap<String, SetupShellCommandRaw>>,
+        kind: CommandKind,
+    ) -> Result<Vec<Self>, String> {
+        raw.map(|raw| {
+            raw.iter()
+                .map(|(name, raw)| Self::make(name, raw, kind))
+                .collect()
+        })
+        .unwrap_or_else(|| Ok(Vec::new()))
+    }
+
+    fn lines(&self) -> impl Iterator<Item = &str> {
+        self.command
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+    }
+}
+
+/// The aliases and functions of a whole graph, later setups winning.
+#[derive(Default)]
+struct Merged<'a> {
+    aliases: BTreeMap<&'a str, &'a ShellCommand>,
+    functions: BTreeMap<&'a str, &'a ShellCommand>,
+}
+
+/// Merge `setups`, given in depth-first order, returning a warning for every
+/// name defined more than once.
+fn merge(setups: &[Setup]) -> (Merged<'_>, Vec<String>) {
+    let mut merged = Merged::default();
+    let mut owners: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for s in setups {
+        for alias in &s.aliases {
+            merged.aliases.insert(&alias.name, alias);
+            owners.entry(&alias.name).or_default().push(&s.name);
+        }
+        for function in &s.functions {
+            merged.functions.insert(&function.name, function);
+            owners.entry(&function.name).or_default().push(&s.name);
+        }
+    }
+    let warnings = owners
+        .into_iter()
+        .filter(|(_, setups)| setups.len() > 1)
+        .map(|(name, setups)| {
+            format!(
+                "{} is defined by {}; using {}",
+                name,
+                setups.join(", "),
+                setups.last().expect("defined at least twice")
+            )
+        })
+        .collect();
+    (merged, warnings)
+}
+
+/// Names defined by more than one of `setups`, as `generate` warns about them.
+pub fn duplicate_warnings(setups: &[Setup]) -> Vec<String> {
+    merge(setups).1
+}
+
+/// Rewrite the `$1`..`$9` and `$@` arguments of a function line.
+fn translate_args(line: &str, positional: impl Fn(&str) -> String, all: &str) -> String {
+    let re = Regex::new(r"\$([1-9@])").expect("valid regex");
+    re.replace_all(line, |caps: &regex::Captures| match &caps[1] {
+        "@" => all.to_string(),
+        n => positional(n),
+    })
+    .to_string()
+}
+
+fn nu_env_vars(line: &str) -> String {
+    let re = Regex::new(r"\$([A-Za-z_][A-Za-z0-9_]*)").expect("valid regex");
+    re.replace_all(line, "$$env.$1").to_string()
+}
+
+/// Guard for entries meant for only one of bash and zsh, which share a file.
+fn sh_guard(shells: &[Shell]) -> Option<&'static str> {
+    match (shells.contains(&Shell::Bash), shells.contains(&Shell::Zsh)) {
+        (true, true) => Some(""),
+        (true, false) => Some("[ -n \"$BASH_VERSION\" ] && "),
+        (false, true) => Some("[ -n \"$ZSH_VERSION\" ] && "),
+        (false, false) => None,
+    }
+}
+
+fn render_sh(merged: &Merged) -> String {
+    let mut out = format!("# {}\n", GENERATED_HEADER);
+    for alias in merged.aliases.values() {
+        if let Some(guard) = sh_guard(&alias.shells) {
+            out.push_str(&format!(
+                "{}alias {}={}\n",
+                guard,
+                alias.name,
+                sh_literal(&alias.command)
+            ));
+        }
+    }
+    for function in merged.functions.values() {
+        let Some(guard) = sh_guard(&function.shells) else {
+            continue;
+        };
+        out.push_str(&format!("{}{} () {{\n", guard, function.name));
+        for line in function.lines() {
+            let line = translate_args(line, |n| format!("\"${}\"", n), "\"$@\"");
+            out.push_str(&format!("    {}\n", line));
+        }
+        out.push_str("}\n");
+    }
+    out
+}
+
+fn render_fish(merged: &Merged) -> String {
+    let mut out = format!("# {}\n", GENERATED_HEADER);
+    for alias in merged.aliases.values() {
+        if alias.shells.contains(&Shell::Fish) {
+            out.push_str(&format!(
+                "alias {} {}\n",
+                alias.name,
+                fish_literal(&alias.command)
+            ));
+        }
+    }
+    for function in merged.functions.values() {
+        if !function.shells.contains(&Shell::Fish) {
+            continue;
+        }
+        out.push_str(&format!("function {}\n", function.name));
+        for line in function.lines() {
+            let line = translate_args(line, |n| format!("$argv[{}]", n), "$argv");
+            out.push_str(&format!("    {}\n", line));
+        }
+        out.push_str("end\n");
+    }
+    out
+}
+
+fn render_nu(merged: &Merged) -> String {
+    let mut out = format!("# {}\n", GENERATED_HEADER);
+    for alias in merged.aliases.values() {
+        if alias.shells.contains(&Shell::Nu) {
+            out.push_str(&format!(
+                "alias {} = {}\n",
+                alias.name,
+                nu_env_vars(&alias.command)
+            ));
+        }
+    }
+    for function in merged.functions.values() {
+        if !function.shells.contains(&Shell::Nu) {
+            continue;
+        }
+        out.push_str(&format!(
+            "def --env --wrapped {} [...args] {{\n",
+            function.name
+        ));
+        for line in function.lines() {
+            let line = nu_env_vars(line);
+            let line = translate_args(
+                &line,
+                |n| format!("$args.{}", n.parse::<usize>().unwrap_or(1) - 1),
+                "...$args",
+            );
+            out.push_str(&format!("    {}\n", line));
+        }
+        out.push_str("}\n");
+    }
+    out
+}
+
+/// Compile the aliases and functions of `setups` for every shell. Names
+/// defined twice only warn; failing to write a file is an error.
+pub fn generate(setups: &[Setup]) -> Result<(), Vec<String>> {
+    println!("{}", "🔤 Aliases".magenta().bold());
+    let (merged, warnings) = merge(setups);
+    for warning in &warnings {
+        println!("    ⚠️  {}", warning.yellow());
+    }
+
+    let dir = get_owl_aliases_path();
+    let files = [
+        ("aliases.sh", render_sh(&merged)),
+        ("aliases.fish", render_fish(&merged)),
+        ("aliases.nu", render_nu(&merged)),
+    ];
+    let mut errors = Vec::new();
+    for (file, contents) in files {
+        let path = dir.join(file);
+        match write_generated(&path, &contents) {
+            Ok(()) => println!("    {} ✅", path.display().to_string().green()),
+            Err(e) => {
+                println!("    {} ❌ {}", path.display().to_string().green(), e);
+                errors.push(e);
+            }
+        }
+    }
+    println!(
+        "    {} aliases, {} functions",
+        merged.aliases.len().to_string().cyan(),
+        merged.functions.len().to_string().cyan()
+    );
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn print_commands(title: &str, commands: &[ShellCommand]) {
+    if commands.is_empty() {
+        return;
+    }
+    print_subsection(title);
+    for c in commands {
+        let shells = if c.shells.len() == Shell::ALL.len() {
+            String::new()
+        } else {
+            let names: Vec<&str> = c.shells.iter().map(|s| s.name()).collect();
+            format!(" ({})", names.join(", "))
+        };
+        println!(
+            "    {} = {}{}",
+            c.name.yellow(),
+            c.command.replace('\n', "; ").green(),
+            shells.dimmed()
+        );
+    }
+}
+
+pub fn print_aliases_plan(aliases: &[ShellCommand], functions: &[ShellCommand]) {
+    print_commands("Aliases", aliases);
+    print_commands("Functions", functions);
+}
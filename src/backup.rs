@@ -0,0 +1,171 @@
+use colored::Colorize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::state::{format_age, now_secs, BackupRecord, OwlState};
+use crate::{
+    get_owl_state_path, get_state, print_section, save_state, tilde_expand_path, unlink_record,
+    LinkingError,
+};
+
+// =======================================
+//            Backups
+// =======================================
+//
+// Files that sit where owl wants to link are moved into
+// `~/.local/state/owl/backups/<timestamp>/<target path>` instead of being deleted.
+
+fn get_backups_path() -> PathBuf {
+    get_owl_state_path().join("backups")
+}
+
+fn move_path(from: &Path, to: &Path, root: bool) -> Result<(), String> {
+    if !root && std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    // rename fails across filesystems and for root-owned files; fall back to mv
+    let mut cmd = if root {
+        let mut c = Command::new("sudo");
+        c.arg("mv");
+        c
+    } else {
+        Command::new("mv")
+    };
+    let output = cmd.arg(from).arg(to).output();
+    match output {
+        Ok(o) if o.status.success() => Ok(()),
+        Ok(o) => Err(format!("mv failed: {}", String::from_utf8_lossy(&o.stderr))),
+        Err(e) => Err(format!("exec mv: {}", e)),
+    }
+}
+
+/// Move whatever sits at `target_path` into the backup store and record it.
+pub fn backup_target(
+    target_path: &Path,
+    root: bool,
+    state: &mut OwlState,
+) -> Result<PathBuf, LinkingError> {
+    let created_at = now_secs();
+    let relative = target_path.strip_prefix("/").unwrap_or(target_path);
+    // Several backups of the same target can happen within one second
+    let mut backup_path = get_backups_path()
+        .join(created_at.to_string())
+        .join(relative);
+    let mut attempt = 1;
+    while backup_path.symlink_metadata().is_ok() {
+        backup_path = get_backups_path()
+            .join(format!("{}-{}", created_at, attempt))
+            .join(relative);
+        attempt += 1;
+    }
+    if let Some(parent) = backup_path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| LinkingError {
+            message: format!("create backup dir: {}", e),
+        })?;
+    }
+    move_path(target_path, &backup_path, root).map_err(|e| LinkingError {
+        message: format!("backup: {}", e),
+    })?;
+
+    state.record_backup(BackupRecord {
+        target: target_path.to_path_buf(),
+        backup: backup_path.clone(),
+        created_at,
+        root,
+    });
+    println!(
+        "    {} {} → {}",
+        "📦 Backed up".yellow(),
+        target_path.display().to_string().green(),
+        backup_path.display().to_string().blue()
+    );
+    Ok(backup_path)
+}
+
+/// Put the most recent backup of `target` back in place.
+pub fn restore(target: &str) {
+    let mut target_path = tilde_expand_path(target);
+    if target_path.is_relative() {
+        if let Ok(cwd) = std::env::current_dir() {
+            target_path = cwd.join(target_path);
+        }
+    }
+
+    let mut state = get_state();
+    let record = match state.latest_backup(&target_path) {
+        Some(r) => r.clone(),
+        None => {
+            eprintln!(
+                "{} {}",
+                "No backup found for".red(),
+                target_path.display().to_string().yellow()
+            );
+            std::process::exit(1);
+        }
+    };
+
+    // Only something owl created (and nobody edited since) may be cleared out of the way
+    if target_path.symlink_metadata().is_ok() {
+        let owned = state.find_link(&target_path).cloned();
+        match owned {
+            Some(link) => {
+                if let Err(e) = unlink_record(&link) {
+                    eprintln!("{} {}", "Unable to remove owl link:".red(), e.message());
+                    std::process::exit(1);
+                }
+                state.forget_link(&link.target);
+            }
+            None => {
+                eprintln!(
+                    "{} {}",
+                    target_path.display().to_string().yellow(),
+                    "exists and is not managed by owl; move it away first".red()
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(parent) = target_path.parent() {
+        if !parent.exists() {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                eprintln!("{} {}", "Unable to create parent dir:".red(), e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Err(e) = move_path(&record.backup, &target_path, record.root) {
+        eprintln!("{} {}", "Restore failed:".red(), e);
+        save_state(&state);
+        std::process::exit(1);
+    }
+    state.forget_backup(&record.backup);
+    save_state(&state);
+
+    println!(
+        "{} {} ← {}",
+        "Restored".green(),
+        target_path.display().to_string().green(),
+        record.backup.display().to_string().blue()
+    );
+}
+
+pub fn print_backups() {
+    let state = get_state();
+    print_section("Backups");
+    if state.backups.is_empty() {
+        println!("  {}", "(none)".yellow());
+        return;
+    }
+    let mut backups = state.backups.clone();
+    backups.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+    for b in backups {
+        println!(
+            "    {} ← {} ({})",
+            b.target.display().to_string().green(),
+            b.backup.display().to_string().blue(),
+            format_age(b.created_at).yellow()
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use colored::Colorize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::shell::{fish_value, nu_value, sh_value, write_generated, GENERATED_HEADER};
+use crate::{graph, print_subsection, tilde_expand_path, Setup};
+
+// =======================================
+//            Environment
+// =======================================
+//
+// Setups declare `env` variables and `path` entries. They are merged over the
+// whole nest graph and rendered once per shell (plus systemd `environment.d`,
+// for graphical sessions) under ~/.config/owl/env/.
+//
+// A setup may override a variable set by anything it depends on. Two setups
+// that do not depend on each other must agree on a value; otherwise the
+// conflict has to be settled by a setup that depends on both.
+
+fn get_owl_env_path() -> PathBuf {
+    tilde_expand_path("~/.config/owl/env")
+}
+
+fn get_environment_d_link_path() -> PathBuf {
+    tilde_expand_path("~/.config/environment.d/90-owl.conf")
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+struct Definition {
+    setup: String,
+    value: String,
+}
+
+/// The environment of a whole graph after overrides are applied.
+#[derive(Default)]
+pub struct MergedEnv {
+    /// Variable name to (value, setup that set it)
+    vars: BTreeMap<String, (String, String)>,
+    /// PATH entries, highest priority first
+    path: Vec<String>,
+}
+
+/// Merge the env and path of `setups`, given in depth-first order (dependencies
+/// before dependents). Returns one message per conflicting variable.
+pub fn merge(setups: &[Setup]) -> Result<MergedEnv, Vec<String>> {
+    let graph = graph::get_dependency_graph();
+    let mut definitions: BTreeMap<&str, Vec<Definition>> = BTreeMap::new();
+    for s in setups {
+        for (name, value) in &s.env {
+            definitions.entry(name).or_default().push(Definition {
+                setup: s.name.clone(),
+                value: value.clone(),
+            });
+        }
+    }
+
+    let mut merged = MergedEnv::default();
+    let mut conflicts = Vec::new();
+    for (name, defs) in definitions {
+        // A definition wins unless another defining setup depends on its setup
+        let winners: Vec<&Definition> = defs
+            .iter()
+            .filter(|d| {
+                !defs.iter().any(|other| {
+                    other.setup != d.setup && graph.reachable(&other.setup).contains(&d.setup)
+                })
+            })
+            .collect();
+        let first = winners[0];
+        if winners.iter().all(|w| w.value == first.value) {
+            merged
+                .vars
+                .insert(name.to_string(), (first.value.clone(), first.setup.clone()));
+        } else {
+            let sides: Vec<String> = winners
+                .iter()
+                .map(|w| format!("{} sets \"{}\"", w.setup, w.value))
+                .collect();
+            conflicts.push(format!("env {}: {}", name, sides.join(", ")));
+        }
+    }
+
+    // Dependents come later in the walk and take precedence in PATH
+    for s in setups.iter().rev() {
+        for entry in &s.path {
+            if !merged.path.contains(entry) {
+                merged.path.push(entry.clone());
+            }
+        }
+    }
+
+    if conflicts.is_empty() {
+        Ok(merged)
+    } else {
+        Err(conflicts)
+    }
+}
+
+fn render_sh(env: &MergedEnv) -> String {
+    let mut out = format!("# {}\n", GENERATED_HEADER);
+    for (name, (value, _)) in &env.vars {
+        out.push_str(&format!("export {}={}\n", name, sh_value(value)));
+    }
+    // Prepend in reverse so the first entry ends up first; skip entries already
+    // present so nested shells do not keep growing PATH
+    for entry in env.path.iter().rev() {
+        out.push_str(&format!(
+            "case \":${{PATH}}:\" in *:{}:*) ;; *) export PATH={} ;; esac\n",
+            sh_value(entry),
+            sh_value(&format!("{}:$PATH", entry))
+        ));
+    }
+    out
+}
+
+fn render_fish(env: &MergedEnv) -> String {
+    let mut out = format!("# {}\n", GENERATED_HEADER);
+    for (name, (value, _)) in &env.vars {
+        out.push_str(&format!("set -gx {} {}\n", name, fish_value(value)));
+    }
+    if !env.path.is_empty() {
+        let entries: Vec<String> = env.path.iter().map(|p| fish_value(p)).collect();
+        out.push_str(&format!(
+            "fish_add_path --global --path {}\n",
+            entries.join(" ")
+        ));
+    }
+    out
+}
+
+fn render_nu(env: &MergedEnv) -> String {
+    let mut out = format!("# {}\n", GENERATED_HEADER);
+    for (name, (value, _)) in &env.vars {
+        out.push_str(&format!("$env.{} = {}\n", name, nu_value(value)));
+    }
+    if !env.path.is_empty() {
+        let entries: Vec<String> = env.path.iter().map(|p| nu_value(p)).collect();
+        out.push_str(&format!(
+            "$env.PATH = ($env.PATH | split row (char esep) | prepend [{}] | uniq)\n",
+            entries.join(" ")
+        ));
+    }
+    out
+}
+
+fn render_environment_d(env: &MergedEnv) -> String {
+    let mut out = format!("# {}\n", GENERATED_HEADER);
+    for (name, (value, _)) in &env.vars {
+        out.push_str(&format!("{}={}\n", name, sh_value(value)));
+    }
+    if !env.path.is_empty() {
+        let value = format!("{}:$PATH", env.path.join(":"));
+        out.push_str(&format!("PATH={}\n", sh_value(&value)));
+    }
+    out
+}
+
+/// Point ~/.config/environment.d at the generated file, leaving anything else there alone.
+fn link_environment_d(source: &Path) -> Result<(), String> {
+    let target = get_environment_d_link_path();
+    match std::fs::read_link(&target) {
+        Ok(existing) if existing == source => return Ok(()),
+        Ok(_) => {
+            std::fs::remove_file(&target)
+                .map_err(|e| format!("remove {}: {}", target.display(), e))?;
+        }
+        Err(_) if target.exists() => {
+            return Err(format!("{} exists and is not owl's link", target.display()));
+        }
+        Err(_) => {}
+    }
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("create {}: {}", parent.display(), e))?;
+    }
+    std::os::unix::fs::symlink(source, &target)
+        .map_err(|e| format!("link {}: {}", target.display(), e))
+}
+
+/// Merge the environment of `setups` and write it for every shell. Nothing is
+/// written when there are conflicts.
+pub fn generate(setups: &[Setup]) -> Result<(), Vec<String>> {
+    println!("{}", "🌱 Environment".magenta().bold());
+    let env = match merge(setups) {
+        Ok(env) => env,
+        Err(conflicts) => {
+            for conflict in &conflicts {
+                println!("    ❌ {}", conflict);
+            }
+            return Err(conflicts);
+        }
+    };
+
+    let dir = get_owl_env_path();
+    let files = [
+        ("env.sh", render_sh(&env)),
+        ("env.fish", render_fish(&env)),
+        ("env.nu", render_nu(&env)),
+        ("environment.conf", render_environment_d(&env)),
+    ];
+    let mut errors = Vec::new();
+    for (file, contents) in files {
+        let path = dir.join(file);
+        match write_generated(&path, &contents) {
+            Ok(()) => println!("    {} ✅", path.display().to_string().green()),
+            Err(e) => {
+                println!("    {} ❌ {}", path.display().to_string().green(), e);
+                errors.push(e);
+            }
+        }
+    }
+    if let Err(e) = link_environment_d(&dir.join("environment.conf")) {
+        println!("    ⚠️  {}", e.yellow());
+    }
+    println!(
+        "    {} variables, {} PATH entries",
+        env.vars.len().to_string().cyan(),
+        env.path.len().to_string().cyan()
+    );
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+pub fn print_env_plan(env: &BTreeMap<String, String>, path: &[String]) {
+    if !env.is_empty() {
+        print_subsection("Environment");
+        for (name, value) in env {
+            println!("    {}={}", name.yellow(), value.green());
+        }
+    }
+    if !path.is_empty() {
+        print_subsection("PATH");
+        for entry in path {
+            println!("    {}", entry.green());
+        }
+    }
+}
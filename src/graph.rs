@@ -0,0 +1,196 @@
+use colored::Colorize;
+use once_cell::sync::OnceCell;
+use std::collections::{BTreeMap, HashSet};
+
+use crate::when::Conditional;
+use crate::{get_config, read_setup_headers_from_dir, setup_file, SetupFileRaw, SetupHeader};
+
+// =======================================
+//            Dependency Graph
+// =======================================
+//
+// Built once from every setup header so dependency checks never have to
+// recursively load and validate setups.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum NodeKind {
+    Setup,
+    Nest,
+}
+
+pub struct DependencyNode {
+    pub kind: NodeKind,
+    pub dependencies: Vec<String>,
+}
+
+pub struct DependencyGraph {
+    nodes: BTreeMap<String, DependencyNode>,
+}
+
+fn read_dependencies(header: &SetupHeader) -> Vec<String> {
+    // Unreadable or invalid files are reported by normal validation; here they
+    // simply contribute no edges
+    setup_file::read::<SetupFileRaw>(&header.setup_file_path)
+        .ok()
+        .and_then(|raw| raw.dependencies)
+        .unwrap_or_default()
+        .iter()
+        .filter(|dep| dep.when().map(|w| w.matches()).unwrap_or(true))
+        .map(|dep| dep.name().to_string())
+        .collect()
+}
+
+impl DependencyGraph {
+    fn load() -> Self {
+        let config = get_config();
+        let mut nodes = BTreeMap::new();
+        // Nests are inserted last so they win name clashes, matching load_setup_by_name
+        let sources = [
+            (config.owl_path.join("setups"), NodeKind::Setup),
+            (config.owl_path.join("nests"), NodeKind::Nest),
+        ];
+        for (dir, kind) in sources {
+            for header in read_setup_headers_from_dir(&dir) {
+                let dependencies = read_dependencies(&header);
+                nodes.insert(header.name, DependencyNode { kind, dependencies });
+            }
+        }
+        Self { nodes }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.nodes.contains_key(name)
+    }
+
+    /// Find a dependency cycle reachable from `start`, returned as the loop of
+    /// names with the first name repeated at the end (e.g. `a → b → a`).
+    pub fn find_cycle(&self, start: &str) -> Option<Vec<String>> {
+        fn visit(
+            graph: &DependencyGraph,
+            name: &str,
+            path: &mut Vec<String>,
+            done: &mut HashSet<String>,
+        ) -> Option<Vec<String>> {
+            if let Some(pos) = path.iter().position(|n| n == name) {
+                let mut cycle = path[pos..].to_vec();
+                cycle.push(name.to_string());
+                return Some(cycle);
+            }
+            if done.contains(name) {
+                return None;
+            }
+            path.push(name.to_string());
+            if let Some(node) = graph.nodes.get(name) {
+                for dep in &node.dependencies {
+                    if let Some(cycle) = visit(graph, dep, path, done) {
+                        return Some(cycle);
+                    }
+                }
+            }
+            path.pop();
+            done.insert(name.to_string());
+            None
+        }
+        visit(self, start, &mut Vec::new(), &mut HashSet::new())
+    }
+
+    pub fn print_tree(&self, root: &str) {
+        fn walk(
+            graph: &DependencyGraph,
+            name: &str,
+            prefix: &str,
+            is_last: bool,
+            is_root: bool,
+            shown: &mut HashSet<String>,
+        ) {
+            let branch = if is_root {
+                ""
+            } else if is_last {
+                "└── "
+            } else {
+                "├── "
+            };
+            let node = graph.nodes.get(name);
+            let label = match node {
+                None => format!("{} {}", name.red(), "(not found)".red()),
+                Some(n) if n.kind == NodeKind::Nest => name.cyan().bold().to_string(),
+                Some(_) => name.green().to_string(),
+            };
+            let deps = node.map(|n| n.dependencies.as_slice()).unwrap_or(&[]);
+            // Diamonds are common (e.g. everything depends on git); expand each node once
+            if !deps.is_empty() && !shown.insert(name.to_string()) {
+                println!("{}{}{} {}", prefix, branch, label, "(see above)".dimmed());
+                return;
+            }
+            println!("{}{}{}", prefix, branch, label);
+
+            let child_prefix = if is_root {
+                String::new()
+            } else if is_last {
+                format!("{}    ", prefix)
+            } else {
+                format!("{}│   ", prefix)
+            };
+            for (i, dep) in deps.iter().enumerate() {
+                let last = i + 1 == deps.len();
+                walk(graph, dep, &child_prefix, last, false, shown);
+            }
+        }
+        walk(self, root, "", true, true, &mut HashSet::new());
+    }
+
+    /// `root` followed by every name reachable from it, each listed once.
+    pub fn reachable(&self, root: &str) -> Vec<String> {
+        let mut reachable = Vec::new();
+        let mut seen = HashSet::new();
+        let mut stack = vec![root.to_string()];
+        while let Some(name) = stack.pop() {
+            if !seen.insert(name.clone()) {
+                continue;
+            }
+            if let Some(node) = self.nodes.get(&name) {
+                stack.extend(node.dependencies.iter().rev().cloned());
+            }
+            reachable.push(name);
+        }
+        reachable
+    }
+
+    pub fn print_dot(&self, root: &str) {
+        let reachable = self.reachable(root);
+
+        println!("digraph owl {{");
+        println!("    rankdir=LR;");
+        println!("    node [shape=box];");
+        for name in &reachable {
+            match self.nodes.get(name) {
+                Some(n) if n.kind == NodeKind::Nest => {
+                    println!("    \"{}\" [style=bold];", name)
+                }
+                Some(_) => println!("    \"{}\";", name),
+                None => println!(
+                    "    \"{}\" [color=red, label=\"{} (not found)\"];",
+                    name, name
+                ),
+            }
+        }
+        for name in &reachable {
+            if let Some(node) = self.nodes.get(name) {
+                for dep in &node.dependencies {
+                    println!("    \"{}\" -> \"{}\";", name, dep);
+                }
+            }
+        }
+        println!("}}");
+    }
+}
+
+static GRAPH: OnceCell<DependencyGraph> = OnceCell::new();
+
+pub fn get_dependency_graph() -> &'static DependencyGraph {
+    GRAPH.get_or_init(DependencyGraph::load)
+}
+
+pub fn format_cycle(cycle: &[String]) -> String {
+    cycle.join(" → ")
+}
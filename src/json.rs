@@ -0,0 +1,395 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::lint::Lint;
+use crate::shell::Shell;
+use crate::state::OwlState;
+use crate::status::{self, StatusEntry};
+use crate::{get_config, get_state, rc, LinkMode, Linkable, ServiceScope, Setup, ShellCommand};
+
+// =======================================
+//            JSON Output
+// =======================================
+//
+// `--json` documents for scripts and the omni menu, built from the same
+// validated `Setup` structures the printers use. Fields are only ever added;
+// `version` goes up when one is renamed or removed.
+
+const VERSION: u32 = 1;
+
+pub fn print<T: Serialize>(doc: &T) {
+    match serde_json::to_string_pretty(doc) {
+        Ok(json) => println!("{}", json),
+        Err(e) => {
+            eprintln!("Unable to serialize output: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// ---------- Config ----------
+
+#[derive(Serialize)]
+pub struct ConfigDoc {
+    version: u32,
+    owl_path: PathBuf,
+    active_nest: Option<PathBuf>,
+}
+
+pub fn config() -> ConfigDoc {
+    let config = get_config();
+    ConfigDoc {
+        version: VERSION,
+        owl_path: config.owl_path,
+        active_nest: config.nest_path,
+    }
+}
+
+// ---------- Info ----------
+
+#[derive(Serialize)]
+pub struct InfoDoc {
+    version: u32,
+    root: String,
+    /// Dependencies first, like every operation walks them
+    setups: Vec<SetupDoc>,
+    /// Shell name to the rc scripts it sources, in order; empty with `--shallow`
+    rc_order: BTreeMap<&'static str, Vec<RcEntryDoc>>,
+}
+
+#[derive(Serialize)]
+struct SetupDoc {
+    name: String,
+    file: PathBuf,
+    dependencies: Vec<String>,
+    links: Vec<LinkDoc>,
+    rc_scripts: Vec<RcScriptDoc>,
+    menu_scripts: Vec<LinkDoc>,
+    services: Vec<ServiceDoc>,
+    install: Option<InstallDoc>,
+    packages: BTreeMap<&'static str, Vec<String>>,
+    secrets: Vec<SecretDoc>,
+    env: BTreeMap<String, String>,
+    path: Vec<String>,
+    aliases: Vec<CommandDoc>,
+    functions: Vec<CommandDoc>,
+    skipped: Vec<SkippedDoc>,
+}
+
+#[derive(Serialize)]
+struct LinkDoc {
+    source: PathBuf,
+    target: PathBuf,
+    /// "symlink", "copy", "hardlink" or "template"
+    mode: &'static str,
+    root: bool,
+}
+
+impl LinkDoc {
+    fn make<T: Linkable>(item: &T) -> Self {
+        Self {
+            source: item.source_path(),
+            target: item.target_path(),
+            mode: item.mode_label().unwrap_or("symlink"),
+            root: item.requires_root(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct RcScriptDoc {
+    source: PathBuf,
+    target: PathBuf,
+    shell: &'static str,
+    order: i32,
+}
+
+#[derive(Serialize)]
+struct ServiceDoc {
+    source: PathBuf,
+    target: PathBuf,
+    scope: &'static str,
+    mode: &'static str,
+    /// Units owl enables and starts; empty for a template without instances
+    units: Vec<String>,
+    enable: bool,
+    start: bool,
+    restart_on_change: bool,
+}
+
+#[derive(Serialize)]
+struct InstallDoc {
+    script: PathBuf,
+    policy: &'static str,
+    serial: bool,
+    /// Why the script would run, or null when it is up to date
+    pending: Option<&'static str>,
+    /// Seconds since the Unix epoch
+    last_run: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct SecretDoc {
+    template: PathBuf,
+    output: PathBuf,
+    provider: &'static str,
+    rendered: bool,
+}
+
+#[derive(Serialize)]
+struct CommandDoc {
+    name: String,
+    command: String,
+    shells: Vec<&'static str>,
+}
+
+impl CommandDoc {
+    fn make(c: &ShellCommand) -> Self {
+        Self {
+            name: c.name.clone(),
+            command: c.command.clone(),
+            shells: c.shells.iter().map(|s| s.name()).collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SkippedDoc {
+    field: &'static str,
+    entry: String,
+    condition: String,
+}
+
+#[derive(Serialize)]
+struct RcEntryDoc {
+    setup: String,
+    source: PathBuf,
+}
+
+fn setup_doc(s: &Setup, skip_menu_scripts: bool, state: &OwlState) -> SetupDoc {
+    let install = s.install_script.as_ref().map(|install| {
+        let last = state.find_install(&s.name);
+        InstallDoc {
+            script: install.path.clone(),
+            policy: install.policy.label(),
+            serial: s.serial,
+            pending: install.pending_reason(last),
+            last_run: last.map(|l| l.ran_at),
+        }
+    });
+    SetupDoc {
+        name: s.name.clone(),
+        file: s.file.clone(),
+        dependencies: s.dependencies.iter().map(|d| d.name.clone()).collect(),
+        links: s.links.iter().map(LinkDoc::make).collect(),
+        rc_scripts: s
+            .rc_scripts
+            .iter()
+            .map(|r| RcScriptDoc {
+                source: r.source_path(),
+                target: r.target_path(),
+                shell: r.shell.name(),
+                order: r.order,
+            })
+            .collect(),
+        menu_scripts: if skip_menu_scripts {
+            Vec::new()
+        } else {
+            s.menu_scripts.iter().map(LinkDoc::make).collect()
+        },
+        services: s
+            .services
+            .iter()
+            .map(|svc| ServiceDoc {
+                source: svc.source_path(),
+                target: svc.target_path(),
+                scope: match svc.scope {
+                    ServiceScope::System => "system",
+                    ServiceScope::User => "user",
+                },
+                mode: match svc.mode {
+                    LinkMode::Symlink => "symlink",
+                    LinkMode::Copy => "copy",
+                    LinkMode::Hardlink => "hardlink",
+                },
+                units: svc.units(),
+                enable: svc.enable,
+                start: svc.start,
+                restart_on_change: svc.restart_on_change,
+            })
+            .collect(),
+        install,
+        packages: s
+            .packages
+            .iter()
+            .map(|(manager, names)| (manager.name(), names.clone()))
+            .collect(),
+        secrets: s
+            .secrets
+            .iter()
+            .map(|secret| SecretDoc {
+                template: secret.template.clone(),
+                output: secret.output.clone(),
+                provider: secret.provider.label(),
+                rendered: secret.is_rendered(),
+            })
+            .collect(),
+        env: s.env.clone(),
+        path: s.path.clone(),
+        aliases: s.aliases.iter().map(CommandDoc::make).collect(),
+        functions: s.functions.iter().map(CommandDoc::make).collect(),
+        skipped: s
+            .skipped
+            .iter()
+            .map(|k| SkippedDoc {
+                field: k.field,
+                entry: k.entry.clone(),
+                condition: k.condition.clone(),
+            })
+            .collect(),
+    }
+}
+
+/// What `info` would show for `root` and, unless `shallow`, its dependencies.
+pub fn info(root: &Setup, shallow: bool) -> InfoDoc {
+    let state = get_state();
+    let mut setups = Vec::new();
+    root.for_each_in_graph(shallow, |s, skip| setups.push(setup_doc(s, skip, &state)));
+
+    let mut rc_order = BTreeMap::new();
+    if !shallow {
+        let graph = root.graph_setups();
+        for shell in Shell::ALL {
+            let entries: Vec<RcEntryDoc> = rc::scripts_for(&graph, shell)
+                .into_iter()
+                .map(|e| RcEntryDoc {
+                    setup: e.setup.to_string(),
+                    source: e.script.source_path(),
+                })
+                .collect();
+            if !entries.is_empty() {
+                rc_order.insert(shell.name(), entries);
+            }
+        }
+    }
+    InfoDoc {
+        version: VERSION,
+        root: root.name.clone(),
+        setups,
+        rc_order,
+    }
+}
+
+// ---------- Status ----------
+
+#[derive(Serialize)]
+pub struct StatusDoc {
+    version: u32,
+    root: String,
+    setups: Vec<SetupStatusDoc>,
+    ok: usize,
+    drifted: usize,
+}
+
+#[derive(Serialize)]
+struct SetupStatusDoc {
+    name: String,
+    file: PathBuf,
+    items: Vec<StatusEntry>,
+}
+
+impl StatusDoc {
+    pub fn is_clean(&self) -> bool {
+        self.drifted == 0
+    }
+}
+
+/// The drift `status` reports for `root` and, unless `shallow`, its dependencies.
+pub fn status(root: &Setup, shallow: bool) -> StatusDoc {
+    let state = get_state();
+    let mut setups = Vec::new();
+    root.for_each_in_graph(shallow, |s, skip| {
+        setups.push(SetupStatusDoc {
+            name: s.name.clone(),
+            file: s.file.clone(),
+            items: status::setup_status_entries(s, skip, &state),
+        })
+    });
+    let (ok, drifted) = setups
+        .iter()
+        .flat_map(|s| &s.items)
+        .fold((0, 0), |(ok, drifted), item| {
+            if item.ok {
+                (ok + 1, drifted)
+            } else {
+                (ok, drifted + 1)
+            }
+        });
+    StatusDoc {
+        version: VERSION,
+        root: root.name.clone(),
+        setups,
+        ok,
+        drifted,
+    }
+}
+
+// ---------- Validate ----------
+
+#[derive(Serialize)]
+pub struct ValidateDoc {
+    version: u32,
+    strict: bool,
+    setups: Vec<ValidatedDoc>,
+    /// Lints across each nest's graph; only with `--strict`
+    graphs: Vec<GraphLintsDoc>,
+    ok: usize,
+    failed: usize,
+    warnings: usize,
+}
+
+#[derive(Serialize)]
+pub struct ValidatedDoc {
+    pub name: String,
+    pub file: PathBuf,
+    /// Null when the setup loads
+    pub error: Option<String>,
+    /// Only with `--strict`
+    pub lints: Vec<Lint>,
+}
+
+impl ValidatedDoc {
+    pub fn failed(&self) -> bool {
+        self.error.is_some() || self.lints.iter().any(Lint::is_error)
+    }
+}
+
+#[derive(Serialize)]
+pub struct GraphLintsDoc {
+    pub nest: String,
+    pub lints: Vec<Lint>,
+}
+
+pub fn validate(
+    strict: bool,
+    setups: Vec<ValidatedDoc>,
+    graphs: Vec<GraphLintsDoc>,
+) -> ValidateDoc {
+    let failed = setups.iter().filter(|s| s.failed()).count();
+    let warnings = setups
+        .iter()
+        .flat_map(|s| &s.lints)
+        .chain(graphs.iter().flat_map(|g| &g.lints))
+        .filter(|l| !l.is_error())
+        .count();
+    ValidateDoc {
+        version: VERSION,
+        strict,
+        ok: setups.len() - failed,
+        failed,
+        warnings,
+        setups,
+        graphs,
+    }
+}
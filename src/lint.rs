@@ -0,0 +1,294 @@
+use colored::Colorize;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::{aliases, env, schema, setup_file, Linkable, Setup, SetupHeader};
+
+// =======================================
+//            Strict Validation
+// =======================================
+//
+// `owl setups-validate --strict` goes beyond what loading a setup checks:
+// unknown fields, link sources that do not exist, and collisions between the
+// setups of a nest graph. Errors make the command fail; warnings are printed
+// but do not.
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Lint {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Lint {
+    fn error(message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            message,
+        }
+    }
+
+    fn warning(message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    pub fn print(&self) {
+        match self.severity {
+            Severity::Error => println!("    ❌ {}", self.message.red()),
+            Severity::Warning => println!("    ⚠️  {}", self.message.yellow()),
+        }
+    }
+}
+
+// ---------- Unknown fields ----------
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                previous
+            } else {
+                1 + previous.min(row[j]).min(row[j + 1])
+            };
+            previous = current;
+        }
+    }
+    row[b.len()]
+}
+
+fn suggest(unknown: &str, known: &[String]) -> Option<String> {
+    known
+        .iter()
+        .map(|k| (edit_distance(unknown, k), k))
+        .filter(|(d, _)| *d <= 2)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, k)| k.clone())
+}
+
+fn check_keys(value: &Value, known: &[String], context: &str, lints: &mut Vec<Lint>) {
+    let Some(object) = value.as_object() else {
+        return;
+    };
+    for key in object.keys().filter(|k| !known.contains(k)) {
+        let hint = suggest(key, known)
+            .map(|s| format!("; did you mean `{}`?", s))
+            .unwrap_or_default();
+        lints.push(Lint::error(format!(
+            "unknown field `{}`{}{}",
+            key, context, hint
+        )));
+    }
+}
+
+fn check_entries(value: Option<&Value>, field: &str, known: &[String], lints: &mut Vec<Lint>) {
+    for (i, entry) in value
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .enumerate()
+    {
+        check_keys(entry, known, &format!(" in {}[{}]", field, i), lints);
+    }
+}
+
+/// Lints that need the file itself rather than the validated setup.
+pub fn lint_file(header: &SetupHeader) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    let Ok(raw) = setup_file::read::<Value>(&header.setup_file_path) else {
+        // Reported by normal validation
+        return lints;
+    };
+
+    // Field names come from the schema, which is generated from the raw types
+    let schema = schema::setup_schema();
+    let fields = |definition| schema::fields(&schema, Some(definition));
+    check_keys(&raw, &schema::fields(&schema, None), "", &mut lints);
+    for (field, definition) in [
+        ("links", "SetupFileLinkRaw"),
+        ("rc_scripts", "SetupRcScriptRaw"),
+        ("menu_scripts", "SetupMenuScriptItemRaw"),
+        ("services", "SetupServiceRaw"),
+        ("dependencies", "SetupDependencyRaw"),
+        ("secrets", "SetupSecretRaw"),
+    ] {
+        check_entries(raw.get(field), field, &fields(definition), &mut lints);
+    }
+    if let Some(install) = raw.get("install") {
+        check_keys(
+            install,
+            &fields("SetupInstallRaw"),
+            " in install",
+            &mut lints,
+        );
+    }
+    let command_fields = fields("SetupShellCommandRaw");
+    for field in ["aliases", "functions"] {
+        for (name, command) in raw
+            .get(field)
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+        {
+            check_keys(
+                command,
+                &command_fields,
+                &format!(" in {}.{}", field, name),
+                &mut lints,
+            );
+        }
+    }
+
+    // `name` is not read; the directory name is the setup's name
+    if let Some(name) = raw.get("name").and_then(Value::as_str) {
+        if name != header.name {
+            lints.push(Lint::warning(format!(
+                "`name` is \"{}\" but the setup is named after its directory, \"{}\"",
+                name, header.name
+            )));
+        }
+    }
+    lints
+}
+
+// ---------- Setup ----------
+
+/// Lints for a single validated setup.
+pub fn lint_setup(setup: &Setup) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    for link in &setup.links {
+        let declared_output =
+            link.build_output || setup.secrets.iter().any(|s| s.output == link.source_path);
+        if !declared_output && !link.source_path.exists() {
+            lints.push(Lint::error(format!(
+                "link source not found: {} (mark sources an install script creates with \"build_output\": true)",
+                link.source_path.display()
+            )));
+        }
+    }
+
+    let mut seen = Vec::new();
+    for dep in &setup.dependencies {
+        if seen.contains(&&dep.name) {
+            lints.push(Lint::warning(format!(
+                "dependency {} is listed twice",
+                dep.name
+            )));
+        }
+        seen.push(&dep.name);
+    }
+
+    for (target, sources) in declared_targets(setup) {
+        if sources.len() > 1 {
+            lints.push(Lint::error(format!(
+                "{} is declared {} times",
+                target.display(),
+                sources.len()
+            )));
+        }
+    }
+    lints
+}
+
+/// Every target `setup` creates, with the source of each declaration.
+fn declared_targets(setup: &Setup) -> BTreeMap<PathBuf, Vec<PathBuf>> {
+    let mut targets: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    let mut add =
+        |source: PathBuf, target: PathBuf| targets.entry(target).or_default().push(source);
+    for l in &setup.links {
+        add(l.source_path(), l.target_path());
+    }
+    for r in &setup.rc_scripts {
+        add(r.source_path(), r.target_path());
+    }
+    for m in &setup.menu_scripts {
+        add(m.source_path(), m.target_path());
+    }
+    for s in &setup.services {
+        add(s.source_path(), s.target_path());
+    }
+    targets
+}
+
+// ---------- Graph ----------
+
+/// Lints across the setups of one nest graph: targets declared by several
+/// setups or nested inside another setup's target, env conflicts and aliases
+/// defined twice.
+pub fn lint_graph(setups: &[Setup]) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    let mut owners: BTreeMap<PathBuf, Vec<(&str, PathBuf)>> = BTreeMap::new();
+    for s in setups {
+        for (target, sources) in declared_targets(s) {
+            for source in sources {
+                owners
+                    .entry(target.clone())
+                    .or_default()
+                    .push((&s.name, source));
+            }
+        }
+    }
+
+    for (target, declared) in &owners {
+        let mut names: Vec<&str> = declared.iter().map(|(name, _)| *name).collect();
+        names.dedup();
+        if names.len() < 2 {
+            // Duplicates within one setup are reported for that setup
+            continue;
+        }
+        let same_source = declared.iter().all(|(_, source)| *source == declared[0].1);
+        let message = format!("{} is declared by {}", target.display(), names.join(", "));
+        if same_source {
+            lints.push(Lint::warning(format!("{} (same source)", message)));
+        } else {
+            lints.push(Lint::error(format!("{} with different sources", message)));
+        }
+    }
+
+    for (outer, outer_declared) in &owners {
+        for (inner, inner_declared) in owners.range(outer.clone()..) {
+            if inner == outer {
+                continue;
+            }
+            if !inner.starts_with(outer) {
+                break;
+            }
+            lints.push(Lint::error(format!(
+                "{} ({}) is inside {} ({})",
+                inner.display(),
+                inner_declared[0].0,
+                outer.display(),
+                outer_declared[0].0
+            )));
+        }
+    }
+
+    if let Err(conflicts) = env::merge(setups) {
+        lints.extend(conflicts.into_iter().map(Lint::error));
+    }
+    lints.extend(
+        aliases::duplicate_warnings(setups)
+            .into_iter()
+            .map(Lint::warning),
+    );
+    lints
+}
@@ -1,13 +1,44 @@
 use clap::{Parser, Subcommand};
 use colored::Colorize;
 use once_cell::sync::OnceCell;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
 use std::fs;
 use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use thiserror::Error;
 
+mod adopt;
+mod aliases;
+mod backup;
+mod env;
+mod graph;
+mod json;
+mod lint;
+mod packages;
+mod parallel;
+mod rc;
+mod report;
+mod scaffold;
+mod schema;
+mod secrets;
+mod setup_file;
+mod shell;
+mod state;
+mod status;
+mod template;
+mod when;
+
+use aliases::{CommandKind, ShellCommand};
+use report::{RunReport, SetupOutcome};
+use secrets::ValidatedSecret;
+use shell::ScriptShell;
+use state::{InstallRecord, LinkRecord, OwlState, UnitRecord};
+use template::TemplateVars;
+use when::{Conditional, When};
+
 // =======================================
 //            Paths
 // =======================================
@@ -32,6 +63,11 @@ fn get_owl_menu_scripts_path() -> PathBuf {
     PathBuf::from(shellexpand::tilde(&owl_menu_scripts_path).to_string())
 }
 
+fn get_owl_state_path() -> PathBuf {
+    let owl_state_path = "~/.local/state/owl";
+    PathBuf::from(shellexpand::tilde(&owl_state_path).to_string())
+}
+
 // =======================================
 //            Config
 // =======================================
@@ -118,22 +154,36 @@ struct SetupHeader {
     name: String,
     setup_dir: PathBuf,
     setup_file_path: PathBuf,
+    format: setup_file::Format,
 }
 
 impl SetupHeader {
     fn new(setup_file_path: PathBuf) -> Result<Self, String> {
-        if setup_file_path.extension().unwrap() != "json" {
-            return Err("setup file must be a JSON file".to_string());
-        }
+        let Some(format) = setup_file::Format::from_path(&setup_file_path) else {
+            return Err("setup file must be setup.json, setup.toml or setup.yaml".to_string());
+        };
         let setup_dir = setup_file_path.parent().unwrap();
         let name = setup_dir.file_name().unwrap().to_str().unwrap().to_string();
         if !setup_file_path.exists() {
             return Err("setup file does not exist".to_string());
         }
+        let found = setup_file::find_all(setup_dir);
+        if found.len() > 1 {
+            let names: Vec<String> = found
+                .iter()
+                .filter_map(|p| p.file_name())
+                .map(|n| n.to_string_lossy().to_string())
+                .collect();
+            return Err(format!(
+                "more than one setup file ({}); keep one",
+                names.join(", ")
+            ));
+        }
         Ok(Self {
             name,
             setup_dir: setup_dir.to_path_buf(),
             setup_file_path: setup_file_path.to_path_buf(),
+            format,
         })
     }
 }
@@ -143,14 +193,20 @@ fn read_setup_headers_from_dir(dir: &Path) -> Vec<SetupHeader> {
     if let Ok(entries) = std::fs::read_dir(dir) {
         for entry in entries.flatten() {
             let setup_dir = entry.path();
-            let setup_file_path = setup_dir.join("setup.json");
-            if setup_dir.is_dir() && setup_file_path.exists() {
-                headers.push(SetupHeader {
-                    name: setup_dir.file_name().unwrap().to_str().unwrap().to_string(),
-                    setup_dir,
-                    setup_file_path,
-                });
-            }
+            // A directory with several setup files is still listed so
+            // validation can report it
+            let Some(setup_file_path) = setup_file::find(&setup_dir) else {
+                continue;
+            };
+            let Some(format) = setup_file::Format::from_path(&setup_file_path) else {
+                continue;
+            };
+            headers.push(SetupHeader {
+                name: setup_dir.file_name().unwrap().to_str().unwrap().to_string(),
+                setup_dir,
+                setup_file_path,
+                format,
+            });
         }
     }
     headers
@@ -160,37 +216,273 @@ fn read_setup_headers_from_dir(dir: &Path) -> Vec<SetupHeader> {
 //              Raw Setup
 // =======================================
 
-#[derive(Debug, Deserialize)]
+// The raw types also generate the JSON Schema `owl schema` prints, so doc
+// comments on them show up as descriptions in editors.
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 struct SetupServiceRaw {
     path: String,
-    #[serde(rename = "type")] // "system" or "user"
+    /// "system" or "user"
+    #[serde(rename = "type")]
     r#type: Option<String>,
-}
-
-#[derive(Debug, Deserialize)]
+    /// "symlink" (default), "copy" or "hardlink"
+    mode: Option<String>,
+    /// Instances of a template unit like foo@.service
+    instances: Option<Vec<String>>,
+    enable: Option<bool>,
+    start: Option<bool>,
+    /// Restart the unit when its file changed since the last run
+    restart_on_change: Option<bool>,
+    when: Option<When>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 struct SetupFileLinkRaw {
     source: String,
     target: String,
     #[serde(default)]
     root: Option<bool>,
+    /// Render the source with template variables instead of symlinking it
+    template: Option<bool>,
+    /// "symlink" (default), "copy" or "hardlink"
+    mode: Option<String>,
+    /// The source is created by an install script, so it may not exist yet
+    build_output: Option<bool>,
+    when: Option<When>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+struct SetupSecretRaw {
+    /// File with provider references, e.g. `op://Dev/Npm/credential`
+    template: String,
+    output: String,
+    /// "op" (default), "pass" or "file"
+    provider: Option<String>,
+    when: Option<When>,
+}
+
+/// An alias or function body, optionally limited to some shells
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(untagged)]
+#[schemars(deny_unknown_fields)]
+enum SetupShellCommandRaw {
+    Simple(String),
+    Detailed {
+        command: String,
+        /// "bash", "zsh", "fish" or "nu"; all by default
+        shells: Option<Vec<String>>,
+    },
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, JsonSchema)]
 #[serde(untagged)]
+#[schemars(deny_unknown_fields)]
 enum SetupMenuScriptItemRaw {
     Simple(String),
-    Detailed { path: String, name: String },
+    Detailed {
+        path: String,
+        name: Option<String>,
+        when: Option<Box<When>>,
+    },
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(untagged)]
+#[schemars(deny_unknown_fields)]
+enum SetupRcScriptRaw {
+    Simple(String),
+    Detailed {
+        path: String,
+        /// "sh", "bash", "zsh", "fish" or "nu"; inferred from the extension by default
+        shell: Option<String>,
+        /// Lower runs earlier; 0 by default
+        order: Option<i32>,
+        when: Option<Box<When>>,
+    },
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(untagged)]
+#[schemars(deny_unknown_fields)]
+enum SetupDependencyRaw {
+    Simple(String),
+    Detailed {
+        name: String,
+        when: Option<Box<When>>,
+    },
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(untagged)]
+#[schemars(deny_unknown_fields)]
+enum SetupInstallRaw {
+    Simple(String),
+    Detailed {
+        path: String,
+        when: Option<Box<When>>,
+    },
 }
 
-#[derive(Debug, Deserialize)]
+/// An owl setup file: setup.json, setup.toml or setup.yaml
+#[derive(Debug, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 struct SetupFileRaw {
-    install: Option<String>,
+    /// Editor schema reference; ignored by owl
+    #[serde(rename = "$schema")]
+    #[allow(dead_code)]
+    schema: Option<String>,
+    /// Informational; a setup is named after its directory
+    #[allow(dead_code)]
+    name: Option<String>,
+    install: Option<SetupInstallRaw>,
     links: Option<Vec<SetupFileLinkRaw>>,
-    rc_scripts: Option<Vec<String>>,
+    rc_scripts: Option<Vec<SetupRcScriptRaw>>,
     menu_scripts: Option<Vec<SetupMenuScriptItemRaw>>,
     services: Option<Vec<SetupServiceRaw>>,
-    dependencies: Option<Vec<String>>,
+    dependencies: Option<Vec<SetupDependencyRaw>>,
     only_own_menu_scripts: Option<bool>,
+    /// On a nest, source one bundle of all rc scripts instead of each script
+    rc_bundle: Option<bool>,
+    /// "on_change" (default), "once" or "always"
+    install_policy: Option<String>,
+    /// Never run the install script alongside other setups
+    serial: Option<bool>,
+    packages: Option<packages::PackageSet>,
+    secrets: Option<Vec<SetupSecretRaw>>,
+    env: Option<BTreeMap<String, String>>,
+    path: Option<Vec<String>>,
+    aliases: Option<BTreeMap<String, SetupShellCommandRaw>>,
+    functions: Option<BTreeMap<String, SetupShellCommandRaw>>,
+    /// Template variables for this setup's templated links
+    vars: Option<TemplateVars>,
+}
+
+impl SetupMenuScriptItemRaw {
+    fn path(&self) -> &str {
+        match self {
+            SetupMenuScriptItemRaw::Simple(path) => path,
+            SetupMenuScriptItemRaw::Detailed { path, .. } => path,
+        }
+    }
+}
+
+impl SetupRcScriptRaw {
+    fn path(&self) -> &str {
+        match self {
+            SetupRcScriptRaw::Simple(path) => path,
+            SetupRcScriptRaw::Detailed { path, .. } => path,
+        }
+    }
+
+    fn shell(&self) -> Option<&str> {
+        match self {
+            SetupRcScriptRaw::Simple(_) => None,
+            SetupRcScriptRaw::Detailed { shell, .. } => shell.as_deref(),
+        }
+    }
+
+    fn order(&self) -> i32 {
+        match self {
+            SetupRcScriptRaw::Simple(_) => 0,
+            SetupRcScriptRaw::Detailed { order, .. } => order.unwrap_or(0),
+        }
+    }
+}
+
+impl SetupDependencyRaw {
+    fn name(&self) -> &str {
+        match self {
+            SetupDependencyRaw::Simple(name) => name,
+            SetupDependencyRaw::Detailed { name, .. } => name,
+        }
+    }
+}
+
+impl SetupInstallRaw {
+    fn path(&self) -> &str {
+        match self {
+            SetupInstallRaw::Simple(path) => path,
+            SetupInstallRaw::Detailed { path, .. } => path,
+        }
+    }
+}
+
+impl Conditional for SetupFileLinkRaw {
+    fn when(&self) -> Option<&When> {
+        self.when.as_ref()
+    }
+    fn describe(&self) -> String {
+        format!("{} → {}", self.source, self.target)
+    }
+}
+
+impl Conditional for SetupSecretRaw {
+    fn when(&self) -> Option<&When> {
+        self.when.as_ref()
+    }
+    fn describe(&self) -> String {
+        format!("{} ⇒ {}", self.template, self.output)
+    }
+}
+
+impl Conditional for SetupServiceRaw {
+    fn when(&self) -> Option<&When> {
+        self.when.as_ref()
+    }
+    fn describe(&self) -> String {
+        self.path.clone()
+    }
+}
+
+impl Conditional for SetupMenuScriptItemRaw {
+    fn when(&self) -> Option<&When> {
+        match self {
+            SetupMenuScriptItemRaw::Simple(_) => None,
+            SetupMenuScriptItemRaw::Detailed { when, .. } => when.as_deref(),
+        }
+    }
+    fn describe(&self) -> String {
+        self.path().to_string()
+    }
+}
+
+impl Conditional for SetupRcScriptRaw {
+    fn when(&self) -> Option<&When> {
+        match self {
+            SetupRcScriptRaw::Simple(_) => None,
+            SetupRcScriptRaw::Detailed { when, .. } => when.as_deref(),
+        }
+    }
+    fn describe(&self) -> String {
+        self.path().to_string()
+    }
+}
+
+impl Conditional for SetupDependencyRaw {
+    fn when(&self) -> Option<&When> {
+        match self {
+            SetupDependencyRaw::Simple(_) => None,
+            SetupDependencyRaw::Detailed { when, .. } => when.as_deref(),
+        }
+    }
+    fn describe(&self) -> String {
+        self.name().to_string()
+    }
+}
+
+impl Conditional for SetupInstallRaw {
+    fn when(&self) -> Option<&When> {
+        match self {
+            SetupInstallRaw::Simple(_) => None,
+            SetupInstallRaw::Detailed { when, .. } => when.as_deref(),
+        }
+    }
+    fn describe(&self) -> String {
+        self.path().to_string()
+    }
 }
 
 // =======================================
@@ -208,12 +500,12 @@ fn tilde_expand_path(input: &str) -> PathBuf {
 fn replace_tokens(input: &str, area: &str, setup_dir: &Path) -> PathBuf {
     if input.starts_with("common:") {
         let input = input.split(":").nth(1).unwrap();
-        return get_config().owl_path.join("common").join(area).join(input);
+        get_config().owl_path.join("common").join(area).join(input)
     } else if input.starts_with("local:") {
         let input = input.split(":").nth(1).unwrap();
-        return setup_dir.join(input);
+        setup_dir.join(input)
     } else {
-        return get_config().owl_path.join(input);
+        get_config().owl_path.join(input)
     }
 }
 
@@ -230,23 +522,96 @@ fn get_filename(path: &Path) -> Result<String, String> {
         .ok_or_else(|| format!("invalid filename: {}", path.display()))
 }
 
+// ---------- Link Modes ----------
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum LinkMode {
+    #[default]
+    Symlink,
+    Copy,
+    Hardlink,
+}
+
+impl LinkMode {
+    fn from_str_or_default(v: Option<&str>) -> Result<Self, String> {
+        match v.map(|m| m.to_lowercase()).as_deref() {
+            None | Some("symlink") => Ok(LinkMode::Symlink),
+            Some("copy") => Ok(LinkMode::Copy),
+            Some("hardlink") => Ok(LinkMode::Hardlink),
+            Some(other) => Err(format!(
+                "invalid link mode '{}' (expected symlink, copy or hardlink)",
+                other
+            )),
+        }
+    }
+}
+
+// ---------- Install Policies ----------
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum InstallPolicy {
+    /// Run until the script succeeds once, then never again
+    Once,
+    /// Re-run whenever the script's contents change
+    #[default]
+    OnChange,
+    /// Run on every install
+    Always,
+}
+
+impl InstallPolicy {
+    fn from_str_or_default(v: Option<&str>) -> Result<Self, String> {
+        match v.map(|p| p.to_lowercase()).as_deref() {
+            None | Some("on_change") => Ok(InstallPolicy::OnChange),
+            Some("once") => Ok(InstallPolicy::Once),
+            Some("always") => Ok(InstallPolicy::Always),
+            Some(other) => Err(format!(
+                "invalid install policy '{}' (expected once, on_change or always)",
+                other
+            )),
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            InstallPolicy::Once => "once",
+            InstallPolicy::OnChange => "on_change",
+            InstallPolicy::Always => "always",
+        }
+    }
+}
+
 // ---------- Setup Links ----------
 struct ValidatedSetupLink {
     source_path: PathBuf,
     target_path: PathBuf,
     root: bool,
+    mode: LinkMode,
+    template_vars: Option<TemplateVars>,
+    build_output: bool,
 }
 
 impl ValidatedSetupLink {
-    fn make(raw: &SetupFileLinkRaw, setup_dir: &Path) -> Result<Self, String> {
+    fn make(raw: &SetupFileLinkRaw, setup_dir: &Path, vars: &TemplateVars) -> Result<Self, String> {
         let source_path = replace_tokens(&tilde_expand(&raw.source), "", setup_dir);
         // Don't validate source existence here - it may be a build output that doesn't exist yet.
-        // The link operation will fail with a clear error if the source is missing.
+        // The link operation will fail with a clear error if the source is missing, and
+        // `setups-validate --strict` reports missing sources not marked `build_output`.
         let target_path = tilde_expand_path(&raw.target);
+        let template_vars = raw.template.unwrap_or(false).then(|| vars.clone());
+        let mode = LinkMode::from_str_or_default(raw.mode.as_deref())?;
+        if template_vars.is_some() && mode == LinkMode::Hardlink {
+            return Err(format!(
+                "template link cannot use hardlink mode: {}",
+                raw.target
+            ));
+        }
         Ok(Self {
             source_path,
             target_path,
             root: raw.root.unwrap_or(false),
+            mode,
+            template_vars,
+            build_output: raw.build_output.unwrap_or(false),
         })
     }
 }
@@ -261,6 +626,12 @@ impl Linkable for ValidatedSetupLink {
     fn requires_root(&self) -> bool {
         self.root
     }
+    fn link_mode(&self) -> LinkMode {
+        self.mode
+    }
+    fn template_vars(&self) -> Option<&TemplateVars> {
+        self.template_vars.as_ref()
+    }
     fn display_info() -> &'static str {
         "Links"
     }
@@ -270,15 +641,31 @@ impl Linkable for ValidatedSetupLink {
 struct ValidatedRunScript {
     name: String,
     path: PathBuf,
+    shell: ScriptShell,
+    order: i32,
 }
 
 impl ValidatedRunScript {
-    fn make(raw: &str, setup_dir: &Path, setup_name: &str) -> Result<Self, String> {
-        let path = replace_tokens(&tilde_expand(raw), "rc", setup_dir);
-        ensure_exists(&path)?;
+    fn make(
+        raw: &SetupRcScriptRaw,
+        setup_dir: &Path,
+        setup_name: &str,
+        secrets: &[ValidatedSecret],
+    ) -> Result<Self, String> {
+        let path = replace_tokens(&tilde_expand(raw.path()), "rc", setup_dir);
+        // Rendered secrets only exist after `owl nest secrets`; linking waits for them
+        if !secrets.iter().any(|s| s.output == path) {
+            ensure_exists(&path)?;
+        }
+        let shell = ScriptShell::from_name_or_path(raw.shell(), &path)?;
         let filename = get_filename(&path)?;
         let name = format!("rc-{}-{}", setup_name, filename);
-        Ok(Self { name, path })
+        Ok(Self {
+            name,
+            path,
+            shell,
+            order: raw.order(),
+        })
     }
 }
 
@@ -287,7 +674,8 @@ impl Linkable for ValidatedRunScript {
         self.path.clone()
     }
     fn target_path(&self) -> PathBuf {
-        get_owl_rc_path().join(&self.name)
+        // One directory per shell, so each loader only sources what it can run
+        get_owl_rc_path().join(self.shell.name()).join(&self.name)
     }
     fn display_info() -> &'static str {
         "RC Scripts"
@@ -302,15 +690,13 @@ struct ValidatedSetupMenuScriptItem {
 
 impl ValidatedSetupMenuScriptItem {
     fn make(raw: &SetupMenuScriptItemRaw, setup_dir: &Path) -> Result<Self, String> {
-        let path: String = match raw {
-            SetupMenuScriptItemRaw::Simple(p) => p.clone(),
-            SetupMenuScriptItemRaw::Detailed { path, .. } => path.clone(),
-        };
-        let path = replace_tokens(&tilde_expand(&path), "menu-scripts", setup_dir);
+        let path = replace_tokens(&tilde_expand(raw.path()), "menu-scripts", setup_dir);
         ensure_exists(&path)?;
         let name: String = match raw {
-            SetupMenuScriptItemRaw::Simple(p) => get_filename(&PathBuf::from(p))?,
-            SetupMenuScriptItemRaw::Detailed { name, .. } => name.clone(),
+            SetupMenuScriptItemRaw::Detailed {
+                name: Some(name), ..
+            } => name.clone(),
+            _ => get_filename(&PathBuf::from(raw.path()))?,
         };
         Ok(Self { path, name })
     }
@@ -329,7 +715,7 @@ impl Linkable for ValidatedSetupMenuScriptItem {
 }
 
 // ---------- Services ----------
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum ServiceScope {
     System,
     User,
@@ -357,57 +743,176 @@ impl ServiceScope {
             }
         }
     }
+    fn systemctl(self) -> Command {
+        match self {
+            ServiceScope::System => {
+                let mut cmd = Command::new("sudo");
+                cmd.arg("systemctl");
+                cmd
+            }
+            ServiceScope::User => {
+                let mut cmd = Command::new("systemctl");
+                cmd.arg("--user");
+                cmd
+            }
+        }
+    }
+    /// Run `systemctl <action> <unit>`.
+    fn run(self, action: &str, unit: &str) -> Result<(), String> {
+        match self.systemctl().arg(action).arg(unit).status() {
+            Ok(s) if s.success() => Ok(()),
+            Ok(s) => Err(format!("{} {}: exit code {:?}", action, unit, s.code())),
+            Err(e) => Err(format!("exec systemctl: {}", e)),
+        }
+    }
+    fn systemctl_prefix(self) -> &'static str {
+        match self {
+            ServiceScope::System => "sudo systemctl",
+            ServiceScope::User => "systemctl --user",
+        }
+    }
 }
 
+const UNIT_TYPES: [&str; 4] = ["service", "timer", "socket", "path"];
+
 struct ValidatedSetupService {
     path: PathBuf,
     scope: ServiceScope,
     name: String,
     target_path: PathBuf,
+    mode: LinkMode,
+    instances: Vec<String>,
+    enable: bool,
+    start: bool,
+    restart_on_change: bool,
 }
 
 impl ValidatedSetupService {
-    fn make(raw: &SetupServiceRaw, setup_dir: &Path) -> Result<Self, String> {
+    fn make(
+        raw: &SetupServiceRaw,
+        setup_dir: &Path,
+        siblings: &[SetupServiceRaw],
+    ) -> Result<Self, String> {
         let scope = ServiceScope::from_str_or_default(raw.r#type.clone());
         let path = replace_tokens(&tilde_expand(&raw.path), "services", setup_dir);
         ensure_exists(&path)?;
         let name: String = get_filename(&path)?;
+        let mode = LinkMode::from_str_or_default(raw.mode.as_deref())?;
+
+        let (stem, unit_type) = name
+            .rsplit_once('.')
+            .filter(|(_, t)| UNIT_TYPES.contains(t))
+            .ok_or_else(|| {
+                format!(
+                    "unsupported unit {} (expected a .service, .timer, .socket or .path file)",
+                    name
+                )
+            })?;
+        let instances = raw.instances.clone().unwrap_or_default();
+        if !instances.is_empty() && !stem.ends_with('@') {
+            return Err(format!(
+                "{} has instances but is not a template unit like {}@.{}",
+                name, stem, unit_type
+            ));
+        }
+        if let Some(bad) = instances
+            .iter()
+            .find(|i| i.is_empty() || i.contains(['/', '@']) || i.contains(char::is_whitespace))
+        {
+            return Err(format!("invalid instance '{}' for {}", bad, name));
+        }
+        // A service activated by a timer, socket or path unit of the same name is left to it
+        let triggered = unit_type == "service"
+            && siblings.iter().any(|s| {
+                Path::new(&s.path)
+                    .file_name()
+                    .and_then(|f| f.to_str())
+                    .is_some_and(|f| {
+                        UNIT_TYPES[1..]
+                            .iter()
+                            .any(|t| f == format!("{}.{}", stem, t))
+                    })
+            });
 
         Ok(Self {
             path: path.clone(),
             scope,
             name: name.to_string(),
-            target_path: scope.get_target_path().join(name),
+            target_path: scope.get_target_path().join(&name),
+            mode,
+            instances,
+            enable: raw.enable.unwrap_or(!triggered),
+            start: raw.start.unwrap_or(!triggered),
+            restart_on_change: raw.restart_on_change.unwrap_or(false),
         })
     }
 
-    fn enable_and_restart(&self) {
-        match self.scope {
-            ServiceScope::System => {
-                let _ = Command::new("sudo")
-                    .arg("systemctl")
-                    .arg("daemon-reload")
-                    .status();
-                let _ = Command::new("sudo")
-                    .arg("systemctl")
-                    .arg("enable")
-                    .arg("--now")
-                    .arg(&self.name)
-                    .status();
+    /// The units to enable and start: each instance of a template, otherwise the
+    /// unit itself. A template without instances is only linked.
+    fn units(&self) -> Vec<String> {
+        match self.name.split_once("@.") {
+            Some((prefix, unit_type)) => self
+                .instances
+                .iter()
+                .map(|i| format!("{}@{}.{}", prefix, i, unit_type))
+                .collect(),
+            None => vec![self.name.clone()],
+        }
+    }
+
+    /// True when `systemd` has anything to do beyond linking.
+    fn is_activated(&self) -> bool {
+        (self.enable || self.start) && !self.units().is_empty()
+    }
+
+    /// The systemctl verbs to run for `unit`, restarting it when its file changed
+    /// since it was last started and `restart_on_change` is set.
+    fn actions(&self, unit: &str, state: &OwlState) -> Vec<&'static str> {
+        let mut actions = Vec::new();
+        if self.enable {
+            actions.push("enable");
+        }
+        if self.start {
+            let changed = state
+                .find_unit(unit, self.scope.is_root())
+                .is_none_or(|u| Some(&u.unit_hash) != state::file_hash(&self.path).as_ref());
+            if self.restart_on_change && changed {
+                actions.push("restart");
+            } else {
+                actions.push("start");
             }
-            ServiceScope::User => {
-                let _ = Command::new("systemctl")
-                    .arg("--user")
-                    .arg("daemon-reload")
-                    .status();
-                let _ = Command::new("systemctl")
-                    .arg("--user")
-                    .arg("enable")
-                    .arg("--now")
-                    .arg(&self.name)
-                    .status();
+        }
+        actions
+    }
+
+    /// Enable and start every unit as configured, recording each one that succeeded.
+    fn activate(&self, setup: &str, state: &mut OwlState) -> Vec<String> {
+        let mut errors = Vec::new();
+        for unit in self.units() {
+            let actions = self.actions(&unit, state);
+            let result = actions
+                .iter()
+                .try_for_each(|action| self.scope.run(action, &unit));
+            match result {
+                Ok(()) => {
+                    println!("    {} ✅ {}", unit.green(), actions.join(", ").dimmed());
+                    state.record_unit(UnitRecord {
+                        unit,
+                        file: self.target_path.clone(),
+                        setup: setup.to_string(),
+                        root: self.scope.is_root(),
+                        unit_hash: state::file_hash(&self.path).unwrap_or_default(),
+                        enabled: self.enable,
+                        started: self.start,
+                    });
+                }
+                Err(e) => {
+                    println!("    {} ❌ {}", unit.green(), e);
+                    errors.push(e);
+                }
             }
         }
+        errors
     }
 }
 
@@ -421,6 +926,9 @@ impl Linkable for ValidatedSetupService {
     fn requires_root(&self) -> bool {
         self.scope.is_root()
     }
+    fn link_mode(&self) -> LinkMode {
+        self.mode
+    }
     fn display_info() -> &'static str {
         "Services"
     }
@@ -434,8 +942,8 @@ struct ValidatedSetupDependency {
 impl ValidatedSetupDependency {
     fn make(raw: &str) -> Result<Self, String> {
         let name = raw.to_string();
-        // Ensure the dependency is a valid setup
-        if load_setup_by_name(&name).is_err() {
+        // Only check that the dependency exists; it is validated when it is loaded itself
+        if !graph::get_dependency_graph().contains(&name) {
             return Err(format!("dependency not found: {}", name));
         }
         Ok(Self { name })
@@ -445,17 +953,32 @@ impl ValidatedSetupDependency {
 // ---------- Install Script ----------
 struct ValidatedSetupInstallScript {
     path: PathBuf,
+    policy: InstallPolicy,
 }
 
 impl ValidatedSetupInstallScript {
-    fn make(raw: &str, setup_dir: &Path) -> Result<Self, String> {
+    fn make(raw: &str, setup_dir: &Path, policy: Option<&str>) -> Result<Self, String> {
         let path = replace_tokens(&tilde_expand(raw), "", setup_dir);
         ensure_exists(&path)?;
-        Ok(Self { path })
+        let policy = InstallPolicy::from_str_or_default(policy)?;
+        Ok(Self { path, policy })
     }
 
-    fn install(&self) {
-        run_script(&self.path);
+    fn hash(&self) -> String {
+        state::file_hash(&self.path).unwrap_or_default()
+    }
+
+    /// Why the script needs to run given its last successful run, or None when it is up to date.
+    fn pending_reason(&self, last: Option<&InstallRecord>) -> Option<&'static str> {
+        match (self.policy, last) {
+            (InstallPolicy::Always, _) => Some("policy is always"),
+            (_, None) => Some("never run"),
+            (InstallPolicy::Once, Some(_)) => None,
+            (InstallPolicy::OnChange, Some(last)) if last.script_hash != self.hash() => {
+                Some("script changed")
+            }
+            (InstallPolicy::OnChange, Some(_)) => None,
+        }
     }
 }
 
@@ -463,11 +986,13 @@ impl ValidatedSetupInstallScript {
 //              Setup
 // =======================================
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 enum Operation {
     Link,
+    Unlink,
     Install,
     Systemd,
+    Secrets,
     Info,
     All,
 }
@@ -476,37 +1001,73 @@ impl Operation {
     fn description(&self) -> &str {
         match self {
             Operation::Link => "🔗 Links",
+            Operation::Unlink => "🧹 Unlink",
             Operation::Install => "📦 Installing",
             Operation::Systemd => "🧩 Systemd",
+            Operation::Secrets => "🔐 Secrets",
             Operation::Info => "ℹ️  Info",
             Operation::All => "🚀 All",
         }
     }
 }
 
+/// A setup entry left out because its `when` clause does not match this machine.
+struct SkippedEntry {
+    field: &'static str,
+    entry: String,
+    condition: String,
+}
+
+/// What a setup's skipped entries would declare. Prune keeps these, so an entry
+/// whose `when` does not match right now (e.g. a `wm: sway` link run from a TTY)
+/// is not mistaken for one removed from the setup.
+#[derive(Default)]
+struct SkippedDeclarations {
+    targets: Vec<PathBuf>,
+    /// Unit names and whether they are system units
+    units: Vec<(String, bool)>,
+    dependencies: Vec<String>,
+}
+
 struct Setup {
     name: String,
     origin_dir: PathBuf,
+    /// setup.json, setup.toml or setup.yaml in `origin_dir`
+    file: PathBuf,
     links: Vec<ValidatedSetupLink>,
     rc_scripts: Vec<ValidatedRunScript>,
     menu_scripts: Vec<ValidatedSetupMenuScriptItem>,
     services: Vec<ValidatedSetupService>,
+    secrets: Vec<ValidatedSecret>,
     dependencies: Vec<ValidatedSetupDependency>,
     install_script: Option<ValidatedSetupInstallScript>,
+    packages: packages::PackageSet,
+    env: BTreeMap<String, String>,
+    /// PATH entries, highest priority first
+    path: Vec<String>,
+    aliases: Vec<ShellCommand>,
+    functions: Vec<ShellCommand>,
     only_own_menu_scripts: bool,
+    /// Concatenate the graph's rc scripts into one bundle per shell (read on the nest)
+    rc_bundle: bool,
+    /// Never run this setup's install script alongside others (e.g. it holds the package manager lock)
+    serial: bool,
+    skipped: Vec<SkippedEntry>,
+    skipped_declarations: SkippedDeclarations,
 }
 
 impl Setup {
-    fn print_op_header(&self, op: Operation) {
+    fn print_op_header(&self, op: Operation, prefix: Option<&str>) {
         let op_description_colored = op.description().magenta().bold();
         let setup_name = self.name.cyan().bold();
-        let setup_dir = self
-            .origin_dir
-            .join("setup.json")
-            .display()
-            .to_string()
-            .green();
-        println!("{} {} ({})", op_description_colored, setup_name, setup_dir);
+        let setup_dir = self.file.display().to_string().green();
+        println!(
+            "{}{} {} ({})",
+            output_tag(prefix),
+            op_description_colored,
+            setup_name,
+            setup_dir
+        );
     }
 
     fn print_linkables_plan<T: Linkable>(&self, items: &[T]) {
@@ -519,92 +1080,288 @@ impl Setup {
             let dst = item.target_path();
             let src_display = src.display().to_string().blue();
             let dst_display = dst.display().to_string().green();
-            println!("    {} → {}", src_display, dst_display);
+            match item.mode_label() {
+                Some(label) => println!(
+                    "    {} {} {} {}",
+                    src_display,
+                    item.arrow(),
+                    dst_display,
+                    format!("({})", label).yellow()
+                ),
+                None => println!("    {} {} {}", src_display, item.arrow(), dst_display),
+            }
+        }
+    }
+
+    fn print_skipped(&self) {
+        if self.skipped.is_empty() {
+            return;
+        }
+        print_subsection("Skipped (when)");
+        for skipped in &self.skipped {
+            println!(
+                "    ⏭️  {} {} {}",
+                format!("{}:", skipped.field).yellow(),
+                skipped.entry.blue(),
+                format!("(when {})", skipped.condition).dimmed()
+            );
         }
     }
 
     fn print_systemd_enable_plan(&self) {
-        if self.services.is_empty() {
+        if !self.services.iter().any(|s| s.is_activated()) {
             return;
         }
-        print_subsection("Services (enable)");
-        for svc in &self.services {
-            match svc.scope {
-                ServiceScope::System => {
-                    println!(
-                        "    {} {}",
-                        "sudo systemctl enable --now".yellow(),
-                        svc.name.green()
-                    );
-                }
-                ServiceScope::User => {
-                    println!(
-                        "    {} {}",
-                        "systemctl --user enable --now".yellow(),
-                        svc.name.green()
-                    );
-                }
+        print_subsection("Units");
+        for svc in self.services.iter().filter(|s| s.is_activated()) {
+            let verb = match (svc.enable, svc.start) {
+                (true, true) => "enable --now",
+                (true, false) => "enable",
+                _ => "start",
+            };
+            let restart = if svc.restart_on_change {
+                " (restart on change)"
+            } else {
+                ""
+            };
+            for unit in svc.units() {
+                println!(
+                    "    {} {}{}",
+                    format!("{} {}", svc.scope.systemctl_prefix(), verb).yellow(),
+                    unit.green(),
+                    restart.dimmed()
+                );
             }
         }
     }
     fn make(setup_raw: &SetupFileRaw, setup_header: &SetupHeader) -> Result<Self, String> {
-        fn validate_vec<T, U>(
+        // Entries whose `when` clause does not match this machine are recorded, not validated
+        fn is_skipped<T: Conditional>(
+            field: &'static str,
+            raw: &T,
+            skipped: &mut Vec<SkippedEntry>,
+        ) -> bool {
+            match raw.when() {
+                Some(when) if !when.matches() => {
+                    skipped.push(SkippedEntry {
+                        field,
+                        entry: raw.describe(),
+                        condition: when.describe(),
+                    });
+                    true
+                }
+                _ => false,
+            }
+        }
+
+        fn validate_vec<T: Conditional, U>(
+            field: &'static str,
             vec: Option<&Vec<T>>,
+            skipped: &mut Vec<SkippedEntry>,
             make: impl Fn(&T) -> Result<U, String>,
         ) -> Result<Vec<U>, String> {
             vec.unwrap_or(&Vec::new())
                 .iter()
-                .map(|t| make(t))
+                .filter(|t| !is_skipped(field, *t, skipped))
+                .map(make)
                 .collect::<Result<Vec<_>, _>>()
         }
 
-        let links = validate_vec(setup_raw.links.as_ref(), |l| {
-            ValidatedSetupLink::make(l, &setup_header.setup_dir)
+        let mut skipped = Vec::new();
+        let vars = template::make_vars(&setup_header.name, setup_raw.vars.as_ref());
+        let secrets = validate_vec("secrets", setup_raw.secrets.as_ref(), &mut skipped, |s| {
+            ValidatedSecret::make(s, &setup_header.setup_dir)
         })?;
-
-        let rc_scripts = validate_vec(setup_raw.rc_scripts.as_ref(), |s| {
-            ValidatedRunScript::make(s, &setup_header.setup_dir, &setup_header.name)
-        })?;
-
-        let menu_scripts = validate_vec(setup_raw.menu_scripts.as_ref(), |s| {
-            ValidatedSetupMenuScriptItem::make(s, &setup_header.setup_dir)
+        let links = validate_vec("links", setup_raw.links.as_ref(), &mut skipped, |l| {
+            ValidatedSetupLink::make(l, &setup_header.setup_dir, &vars)
         })?;
 
-        let services = validate_vec(setup_raw.services.as_ref(), |s| {
-            ValidatedSetupService::make(s, &setup_header.setup_dir)
+        let rc_scripts = validate_vec(
+            "rc_scripts",
+            setup_raw.rc_scripts.as_ref(),
+            &mut skipped,
+            |s| ValidatedRunScript::make(s, &setup_header.setup_dir, &setup_header.name, &secrets),
+        )?;
+
+        let menu_scripts = validate_vec(
+            "menu_scripts",
+            setup_raw.menu_scripts.as_ref(),
+            &mut skipped,
+            |s| ValidatedSetupMenuScriptItem::make(s, &setup_header.setup_dir),
+        )?;
+
+        let services = validate_vec("services", setup_raw.services.as_ref(), &mut skipped, |s| {
+            let siblings = setup_raw.services.as_deref().unwrap_or_default();
+            ValidatedSetupService::make(s, &setup_header.setup_dir, siblings)
         })?;
 
-        let dependencies = validate_vec(setup_raw.dependencies.as_ref(), |s| {
-            ValidatedSetupDependency::make(s)
-        })?;
+        let dependencies = validate_vec(
+            "dependencies",
+            setup_raw.dependencies.as_ref(),
+            &mut skipped,
+            |s| ValidatedSetupDependency::make(s.name()),
+        )?;
+        if let Some(cycle) = graph::get_dependency_graph().find_cycle(&setup_header.name) {
+            return Err(format!("dependency cycle: {}", graph::format_cycle(&cycle)));
+        }
 
         let install_script = setup_raw
             .install
             .as_ref()
-            .map(|install| ValidatedSetupInstallScript::make(install, &setup_header.setup_dir))
+            .filter(|install| !is_skipped("install", *install, &mut skipped))
+            .map(|install| {
+                ValidatedSetupInstallScript::make(
+                    install.path(),
+                    &setup_header.setup_dir,
+                    setup_raw.install_policy.as_deref(),
+                )
+            })
             .transpose()?;
 
+        let packages = setup_raw.packages.clone().unwrap_or_default();
+        for (manager, names) in &packages {
+            if names.iter().any(|n| n.trim().is_empty()) {
+                return Err(format!("empty package name under {}", manager.name()));
+            }
+        }
+
+        let env = setup_raw.env.clone().unwrap_or_default();
+        for name in env.keys() {
+            if name == "PATH" {
+                return Err("set PATH with the `path` field, not `env`".to_string());
+            }
+            if !env::is_valid_name(name) {
+                return Err(format!("invalid environment variable name: {}", name));
+            }
+        }
+        let path = setup_raw
+            .path
+            .as_ref()
+            .unwrap_or(&Vec::new())
+            .iter()
+            .map(|entry| {
+                if entry.trim().is_empty() {
+                    return Err("empty path entry".to_string());
+                }
+                if entry.starts_with("local:") || entry.starts_with("common:") {
+                    Ok(replace_tokens(entry, "", &setup_header.setup_dir)
+                        .display()
+                        .to_string())
+                } else if let Some(rest) = entry
+                    .strip_prefix('~')
+                    .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+                {
+                    // Entries end up joined into one PATH value, where `~` would not expand
+                    Ok(format!("$HOME{}", rest))
+                } else {
+                    Ok(entry.clone())
+                }
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let aliases = ShellCommand::make_all(setup_raw.aliases.as_ref(), CommandKind::Alias)?;
+        let functions =
+            ShellCommand::make_all(setup_raw.functions.as_ref(), CommandKind::Function)?;
+
+        // Skipped entries are not validated; the ones that would not validate
+        // declare nothing
+        fn unmatched<T: Conditional>(vec: Option<&Vec<T>>) -> impl Iterator<Item = &T> {
+            vec.into_iter()
+                .flatten()
+                .filter(|t| t.when().is_some_and(|w| !w.matches()))
+        }
+        let dir = &setup_header.setup_dir;
+        let mut skipped_declarations = SkippedDeclarations::default();
+        let declarations = &mut skipped_declarations;
+        declarations.targets.extend(
+            unmatched(setup_raw.links.as_ref())
+                .filter_map(|l| ValidatedSetupLink::make(l, dir, &vars).ok())
+                .map(|l| l.target_path()),
+        );
+        declarations.targets.extend(
+            unmatched(setup_raw.rc_scripts.as_ref())
+                .filter_map(|s| ValidatedRunScript::make(s, dir, &setup_header.name, &secrets).ok())
+                .map(|s| s.target_path()),
+        );
+        declarations.targets.extend(
+            unmatched(setup_raw.menu_scripts.as_ref())
+                .filter_map(|s| ValidatedSetupMenuScriptItem::make(s, dir).ok())
+                .map(|s| s.target_path()),
+        );
+        let siblings = setup_raw.services.as_deref().unwrap_or_default();
+        for svc in unmatched(setup_raw.services.as_ref())
+            .filter_map(|s| ValidatedSetupService::make(s, dir, siblings).ok())
+        {
+            declarations.targets.push(svc.target_path());
+            if svc.is_activated() {
+                let root = svc.scope.is_root();
+                declarations
+                    .units
+                    .extend(svc.units().into_iter().map(|u| (u, root)));
+            }
+        }
+        declarations
+            .dependencies
+            .extend(unmatched(setup_raw.dependencies.as_ref()).map(|d| d.name().to_string()));
+
         Ok(Setup {
             name: setup_header.name.clone(),
             origin_dir: setup_header.setup_dir.clone(),
+            file: setup_header.setup_file_path.clone(),
             links,
             rc_scripts,
             menu_scripts,
             services,
+            secrets,
             dependencies,
             install_script,
+            packages,
+            env,
+            path,
+            aliases,
+            functions,
             only_own_menu_scripts: setup_raw.only_own_menu_scripts.unwrap_or(false),
+            rc_bundle: setup_raw.rc_bundle.unwrap_or(false),
+            serial: setup_raw.serial.unwrap_or(false),
+            skipped,
+            skipped_declarations,
         })
     }
 
+    fn convert(&self, to: setup_file::Format) -> bool {
+        let target = match setup_file::convert(&self.file, to) {
+            Ok(target) => target,
+            Err(e) => {
+                eprintln!("{} {}", "Unable to convert:".red(), e);
+                return false;
+            }
+        };
+        println!(
+            "{} {} → {}",
+            "✓ Converted".green(),
+            self.file.display(),
+            target.display()
+        );
+        if setup_file::Format::from_path(&self.file) != Some(setup_file::Format::Json) {
+            println!("  {}", "Comments were not carried over".yellow());
+        }
+        let owl_path = get_config().owl_path;
+        if owl_path.join(schema::SCHEMA_FILE).exists() {
+            if let Err(e) = schema::add_reference(&target, &owl_path) {
+                eprintln!("  {} {}", "Unable to add $schema:".red(), e);
+            }
+        }
+        true
+    }
+
     fn edit(&self) {
-        let links_path = self.origin_dir.join("setup.json");
+        let setup_file = &self.file;
         let editor = std::env::var("VISUAL")
             .ok()
             .or_else(|| std::env::var("EDITOR").ok())
             .unwrap_or_else(|| "vim".to_string());
         let mut cmd = Command::new(editor);
-        cmd.arg(&links_path);
+        cmd.arg(setup_file);
         match cmd.status() {
             Ok(status) => {
                 if !status.success() {
@@ -615,9 +1372,11 @@ impl Setup {
         }
     }
 
-    fn run_linkables<T: Linkable>(items: &[T]) {
+    /// Link every item, returning a message for each one that failed.
+    fn run_linkables<T: Linkable>(&self, items: &[T], state: &mut OwlState) -> Vec<String> {
+        let mut errors = Vec::new();
         if items.is_empty() {
-            return;
+            return errors;
         }
         print_subsection(T::display_info());
         for item in items {
@@ -625,103 +1384,640 @@ impl Setup {
             let dst = item.target_path();
             let src_display = src.display().to_string().blue();
             let dst_display = dst.display().to_string().green();
-            match item.link() {
-                Ok(()) => println!("    {} → {} {}", src_display, dst_display, "✅"),
-                Err(e) => println!(
-                    "    {} → {} {} {}",
-                    src_display, dst_display, "❌", e.message
-                ),
+            let arrow = item.arrow();
+            if self.is_pending_secret(&src) {
+                println!(
+                    "    {} {} {} ⏳ {}",
+                    src_display,
+                    arrow,
+                    dst_display,
+                    "secret not rendered yet, run `owl nest secrets`".yellow()
+                );
+                continue;
+            }
+            match item.link(state) {
+                Ok(content_hash) => {
+                    state.record_link(LinkRecord {
+                        source: src.clone(),
+                        target: dst.clone(),
+                        setup: self.name.clone(),
+                        root: item.requires_root(),
+                        mode: item.link_mode(),
+                        content_hash,
+                    });
+                    println!("    {} {} {} ✅", src_display, arrow, dst_display)
+                }
+                Err(e) => {
+                    println!(
+                        "    {} {} {} ❌ {}",
+                        src_display, arrow, dst_display, e.message
+                    );
+                    errors.push(format!("link {}: {}", dst.display(), e.message));
+                }
+            }
+        }
+        errors
+    }
+
+    /// True when `path` is the output of one of this setup's secrets that has not been rendered.
+    fn is_pending_secret(&self, path: &Path) -> bool {
+        self.secrets
+            .iter()
+            .any(|s| s.output == path && !s.is_rendered())
+    }
+
+    fn secrets_once(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.secrets.is_empty() {
+            return errors;
+        }
+        print_subsection("Secrets");
+        for secret in &self.secrets {
+            let result = secret.render();
+            secrets::print_secret_result(secret, &result);
+            if let Err(e) = result {
+                errors.push(format!("secret {}: {}", secret.output.display(), e));
+            }
+        }
+        errors
+    }
+
+    fn link_once(&self, skip_menu_scripts: bool) -> Vec<String> {
+        let mut state = get_state();
+        let mut errors = self.run_linkables(&self.links, &mut state);
+        errors.extend(self.run_linkables(&self.rc_scripts, &mut state));
+        if !skip_menu_scripts {
+            errors.extend(self.run_linkables(&self.menu_scripts, &mut state));
+        }
+        errors.extend(self.run_linkables(&self.services, &mut state));
+        save_state(&state);
+        errors
+    }
+
+    fn unlink_once(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        let mut state = get_state();
+        let records = state.links_for_setup(&self.name);
+        if records.is_empty() {
+            println!("  {}", "No links owned by owl".yellow());
+            return errors;
+        }
+        print_subsection("Owned Links");
+        for record in records {
+            let result = unlink_record(&record);
+            if let Err(e) = &result {
+                errors.push(format!(
+                    "unlink {}: {}",
+                    record.target.display(),
+                    e.message()
+                ));
+            }
+            if !result.as_ref().is_err_and(UnlinkError::keeps_record) {
+                state.forget_link(&record.target);
             }
+            print_unlink_result(&record, result);
         }
+        save_state(&state);
+        errors
     }
 
-    fn link_once(&self, skip_menu_scripts: bool) {
-        Self::run_linkables(&self.links);
-        Self::run_linkables(&self.rc_scripts);
+    /// Every target path this setup would link, used to decide which owned links are orphans.
+    fn declared_targets(&self, skip_menu_scripts: bool) -> Vec<PathBuf> {
+        let mut targets: Vec<PathBuf> = Vec::new();
+        targets.extend(self.links.iter().map(|l| l.target_path()));
+        targets.extend(self.rc_scripts.iter().map(|r| r.target_path()));
         if !skip_menu_scripts {
-            Self::run_linkables(&self.menu_scripts);
+            targets.extend(self.menu_scripts.iter().map(|m| m.target_path()));
         }
-        Self::run_linkables(&self.services);
+        targets.extend(self.services.iter().map(|s| s.target_path()));
+        targets.extend(self.skipped_declarations.targets.iter().cloned());
+        targets
     }
 
-    fn install_once(&self) {
-        if let Some(script) = &self.install_script {
-            println!("Installing {}", self.name.green());
-            script.install();
+    /// Run the install script if its policy calls for it. `prefix` tags every
+    /// output line, for when several scripts run at once.
+    fn install_once(&self, args: &InstallArgs, prefix: Option<&str>) -> Result<(), String> {
+        if args.only.as_ref().is_some_and(|only| only != &self.name) {
+            return Ok(());
+        }
+        packages::install_language_packages(&self.packages, prefix)?;
+        let Some(script) = &self.install_script else {
+            return Ok(());
+        };
+        let tag = output_tag(prefix);
+        let last = get_state().find_install(&self.name).cloned();
+        let reason = if args.force {
+            Some("forced")
+        } else {
+            script.pending_reason(last.as_ref())
+        };
+        let Some(reason) = reason else {
+            let ran_at = last
+                .map(|l| state::format_age(l.ran_at))
+                .unwrap_or_default();
+            println!(
+                "{}  {} {}",
+                tag,
+                "Install script up to date, skipping".green(),
+                format!("(last run {}, use --force to re-run)", ran_at).dimmed()
+            );
+            return Ok(());
+        };
+
+        println!("{}Installing {} ({})", tag, self.name.green(), reason);
+        let script_hash = script.hash();
+        run_script(&script.path, prefix).map_err(|e| format!("install: {}", e))?;
+        // Reload rather than reuse: the script may have run for a long time, alongside others
+        let record = InstallRecord {
+            setup: self.name.clone(),
+            script: script.path.clone(),
+            script_hash,
+            ran_at: state::now_secs(),
+            exit_code: 0,
+        };
+        if let Err(e) = OwlState::update(|state| state.record_install(record)) {
+            eprintln!("{}{} {}", tag, "Error saving owl state:".red(), e);
         }
+        Ok(())
     }
 
     fn info_once(&self) {
         // Dry-run Links
-        self.print_op_header(Operation::Link);
+        self.print_op_header(Operation::Link, None);
         self.print_linkables_plan(&self.links);
         self.print_linkables_plan(&self.rc_scripts);
         self.print_linkables_plan(&self.menu_scripts);
         self.print_linkables_plan(&self.services);
+        env::print_env_plan(&self.env, &self.path);
+        aliases::print_aliases_plan(&self.aliases, &self.functions);
+
+        // Dry-run Secrets
+        if !self.secrets.is_empty() {
+            self.print_op_header(Operation::Secrets, None);
+            print_subsection("Secrets");
+            for secret in &self.secrets {
+                let state = if secret.is_rendered() {
+                    "rendered".green()
+                } else {
+                    "not rendered".yellow()
+                };
+                println!(
+                    "    {} ⇒ {} {} {}",
+                    secret.template.display().to_string().blue(),
+                    secret.output.display().to_string().green(),
+                    format!("({})", secret.provider.label()).yellow(),
+                    state
+                );
+            }
+        }
 
         // Dry-run Install
+        if self.install_script.is_some() || !self.packages.is_empty() {
+            self.print_op_header(Operation::Install, None);
+            packages::print_packages_plan(&self.packages);
+        }
         if let Some(install) = &self.install_script {
-            self.print_op_header(Operation::Install);
             print_subsection("Install Script");
             println!(
-                "    {} {}",
+                "    {} {} {}",
                 "Run:".yellow(),
-                install.path.display().to_string().green()
+                install.path.display().to_string().green(),
+                format!(
+                    "({}{})",
+                    install.policy.label(),
+                    if self.serial { ", serial" } else { "" }
+                )
+                .yellow()
             );
+            let state = get_state();
+            let last = state.find_install(&self.name);
+            match install.pending_reason(last) {
+                Some(reason) => println!("    {} {}", "Pending:".yellow(), reason),
+                None => println!(
+                    "    {} {}",
+                    "Up to date:".green(),
+                    last.map(|l| format!("last run {}", state::format_age(l.ran_at)))
+                        .unwrap_or_default()
+                ),
+            }
         }
 
         // Dry-run Systemd
         if !self.services.is_empty() {
-            self.print_op_header(Operation::Systemd);
+            self.print_op_header(Operation::Systemd, None);
             self.print_linkables_plan(&self.services);
             self.print_systemd_enable_plan();
         }
+
+        self.print_skipped();
     }
 
-    fn systemd_once(&self) {
-        Self::run_linkables(&self.services);
-        for svc in &self.services {
-            svc.enable_and_restart();
+    fn systemd_once(&self) -> Vec<String> {
+        let mut state = get_state();
+        let mut errors = self.run_linkables(&self.services, &mut state);
+        for scope in [ServiceScope::User, ServiceScope::System] {
+            if self.services.iter().any(|s| s.scope == scope) {
+                let _ = scope.systemctl().arg("daemon-reload").status();
+            }
+        }
+        if self.services.iter().any(|s| s.is_activated()) {
+            print_subsection("Units");
         }
+        for svc in self.services.iter().filter(|s| s.is_activated()) {
+            errors.extend(svc.activate(&self.name, &mut state));
+        }
+        save_state(&state);
+        errors
     }
 
-    fn apply_operation_once(&self, op: Operation, skip_menu_scripts: bool) {
+    /// Run `op` on this setup alone, returning a message for everything that failed.
+    fn apply_operation_once(
+        &self,
+        op: Operation,
+        skip_menu_scripts: bool,
+        install: &InstallArgs,
+    ) -> Vec<String> {
         let op_description = op.description();
         let op_description_colored = op_description.magenta().bold();
         let setup_name = self.name.cyan().bold();
-        let setup_dir = self
-            .origin_dir
-            .join("setup.json")
-            .display()
-            .to_string()
-            .green();
+        let setup_dir = self.file.display().to_string().green();
 
         println!("{} {} ({})", op_description_colored, setup_name, setup_dir);
         match op {
             Operation::Link => self.link_once(skip_menu_scripts),
-            Operation::Install => self.install_once(),
+            Operation::Unlink => self.unlink_once(),
+            Operation::Install => self.install_once(install, None).err().into_iter().collect(),
             Operation::Systemd => self.systemd_once(),
-            Operation::Info => self.info_once(),
+            Operation::Secrets => self.secrets_once(),
+            Operation::Info => {
+                self.info_once();
+                Vec::new()
+            }
             Operation::All => {
-                self.link_once(skip_menu_scripts);
-                self.install_once();
-                self.systemd_once();
+                let mut errors = self.link_once(skip_menu_scripts);
+                errors.extend(self.install_once(install, None).err());
+                errors.extend(self.systemd_once());
+                errors
             }
         }
     }
 
-    fn run_op(&self, op: Operation, shallow: bool) {
+    /// Visit this setup (and, unless `shallow`, its dependencies depth-first) together
+    /// with whether inherited menu scripts should be skipped for each one.
+    fn for_each_in_graph<F>(&self, shallow: bool, mut f: F)
+    where
+        F: FnMut(&Setup, bool),
+    {
         if shallow {
-            self.apply_operation_once(op, false);
+            f(self, false);
         } else {
             let skip_inherited_menu_scripts = self.only_own_menu_scripts;
             for_each_dep_depth_first(&self.name, |s| {
                 // Skip menu scripts for dependencies if the root setup has only_own_menu_scripts set
                 let is_root = s.name == self.name;
                 let skip = skip_inherited_menu_scripts && !is_root;
-                s.apply_operation_once(op, skip);
+                f(&s, skip);
+            });
+        }
+    }
+
+    /// Like `for_each_in_graph`, but also visits dependencies skipped by `when`, so
+    /// prune sees everything the graph declares whatever machine or session it runs in.
+    fn for_each_declared<F>(&self, mut f: F)
+    where
+        F: FnMut(&Setup, bool),
+    {
+        let mut visited = HashSet::new();
+        let mut pending = Vec::new();
+        let skip_inherited_menu_scripts = self.only_own_menu_scripts;
+        self.for_each_in_graph(false, |s, skip| {
+            visited.insert(s.name.clone());
+            pending.extend(s.skipped_declarations.dependencies.iter().cloned());
+            f(s, skip);
+        });
+        while let Some(name) = pending.pop() {
+            if !visited.insert(name.clone()) {
+                continue;
+            }
+            // A skipped dependency that does not load declares nothing
+            let Ok(setup) = load_setup_by_name(&name) else {
+                continue;
+            };
+            pending.extend(setup.dependencies.iter().map(|d| d.name.clone()));
+            pending.extend(setup.skipped_declarations.dependencies.iter().cloned());
+            f(&setup, skip_inherited_menu_scripts);
+        }
+    }
+
+    /// The first dependency that failed (or was skipped) earlier in this run, unless
+    /// `keep_going` says to run regardless.
+    fn failed_dependency(&self, report: &RunReport, keep_going: bool) -> Option<&str> {
+        if keep_going {
+            return None;
+        }
+        self.dependencies
+            .iter()
+            .map(|d| d.name.as_str())
+            .find(|name| report.is_broken(name))
+    }
+
+    /// Apply `op` across the graph. Unless `keep_going` is set, setups whose
+    /// dependencies failed are skipped. Returns true when nothing failed.
+    fn run_op(&self, op: Operation, opts: &RunOptions) -> bool {
+        if let Some(only) = &opts.install.only {
+            let in_graph = if opts.shallow {
+                only == &self.name
+            } else {
+                graph::get_dependency_graph()
+                    .reachable(&self.name)
+                    .contains(only)
+            };
+            if !in_graph {
+                eprintln!(
+                    "{} {} {} {}",
+                    "Setup".red(),
+                    only.yellow(),
+                    "is not part of the graph of".red(),
+                    self.name.yellow()
+                );
+                std::process::exit(1);
+            }
+        }
+
+        let mut report = RunReport::default();
+        if matches!(op, Operation::Install | Operation::All) {
+            let mut wanted = Vec::new();
+            self.for_each_in_graph(opts.shallow, |s, _| {
+                let excluded = opts.install.only.as_ref().is_some_and(|o| o != &s.name);
+                if !excluded {
+                    wanted.push((s.name.clone(), s.packages.clone()));
+                }
+            });
+            for (name, error) in packages::install_system_packages(&wanted) {
+                if !report.contains(&name) {
+                    report.record(&name, SetupOutcome::Failed(vec![error]));
+                }
+            }
+        }
+
+        if op == Operation::Install && opts.jobs > 1 && !opts.shallow {
+            parallel::run_installs(&self.graph_setups(), opts, &mut report);
+        } else {
+            self.for_each_in_graph(opts.shallow, |s, skip_menu_scripts| {
+                if report.contains(&s.name) {
+                    return;
+                }
+                if let Some(dep) = s.failed_dependency(&report, opts.keep_going) {
+                    report.skip(&s.name, dep);
+                    return;
+                }
+                let errors = s.apply_operation_once(op, skip_menu_scripts, &opts.install);
+                report.record(&s.name, SetupOutcome::from_errors(errors));
             });
         }
+
+        if matches!(op, Operation::Link | Operation::All) && self.is_active_nest() {
+            self.generate_shell_files(&mut report);
+        }
+
+        if op == Operation::Info && !opts.shallow {
+            rc::print_order(&self.graph_setups());
+        }
+
+        if op != Operation::Info {
+            report.print_summary();
+        }
+        report.is_success()
+    }
+
+    /// Every setup in the graph, dependencies first.
+    fn graph_setups(&self) -> Vec<Setup> {
+        let mut setups = Vec::new();
+        for_each_dep_depth_first(&self.name, |s| setups.push(s));
+        setups
+    }
+
+    /// Regenerate the environment, aliases and shell loaders. They always cover the
+    /// whole graph, so this only runs for the active nest.
+    fn generate_shell_files(&self, report: &mut RunReport) {
+        let setups = self.graph_setups();
+        let errors = env::generate(&setups).err().unwrap_or_default();
+        report.record("environment", SetupOutcome::from_errors(errors));
+        let errors = aliases::generate(&setups).err().unwrap_or_default();
+        report.record("aliases", SetupOutcome::from_errors(errors));
+        let errors = rc::generate_loaders(&setups, self.rc_bundle)
+            .err()
+            .unwrap_or_default();
+        report.record("shell loaders", SetupOutcome::from_errors(errors));
+    }
+
+    fn is_active_nest(&self) -> bool {
+        get_config().nest_path.as_deref() == Some(self.origin_dir.as_path())
+    }
+
+    /// Report drift between what the graph declares and what is on disk.
+    /// Returns true when everything matches.
+    fn status(&self, shallow: bool) -> bool {
+        let state = get_state();
+        let mut counts = status::StatusCounts::default();
+        self.for_each_in_graph(shallow, |s, skip| {
+            println!(
+                "{} {} ({})",
+                "📋 Status".magenta().bold(),
+                s.name.cyan().bold(),
+                s.file.display().to_string().green()
+            );
+            status::print_setup_status(s, skip, &state, &mut counts);
+        });
+
+        println!(
+            "\nStatus: {} ok, {} drifted",
+            counts.ok.to_string().green(),
+            counts.drifted.to_string().red()
+        );
+        counts.drifted == 0
+    }
+
+    /// Units the graph enables or starts, with whether each is a system unit.
+    fn declared_units(&self) -> HashSet<(String, bool)> {
+        let mut declared = HashSet::new();
+        self.for_each_declared(|s, _| {
+            for svc in s.services.iter().filter(|svc| svc.is_activated()) {
+                declared.extend(svc.units().into_iter().map(|u| (u, svc.scope.is_root())));
+            }
+            declared.extend(s.skipped_declarations.units.iter().cloned());
+        });
+        declared
+    }
+
+    /// Units owl enabled or started that the graph no longer declares.
+    fn orphaned_units(&self, state: &OwlState) -> Vec<UnitRecord> {
+        let declared = self.declared_units();
+        state
+            .units
+            .iter()
+            .filter(|u| !declared.contains(&(u.unit.clone(), u.root)))
+            .cloned()
+            .collect()
+    }
+
+    /// Show whether each declared unit is enabled and active, and which units
+    /// owl enabled that are no longer declared. Returns true when nothing drifted.
+    fn systemd_status(&self, shallow: bool) -> bool {
+        let mut counts = status::StatusCounts::default();
+        self.for_each_in_graph(shallow, |s, _| {
+            if !s.services.iter().any(|svc| svc.is_activated()) {
+                return;
+            }
+            println!(
+                "{} {} ({})",
+                "📋 Systemd Status".magenta().bold(),
+                s.name.cyan().bold(),
+                s.file.display().to_string().green()
+            );
+            status::print_services_status(&s.services, &mut counts);
+        });
+        if !shallow {
+            let orphans = self.orphaned_units(&get_state());
+            if !orphans.is_empty() {
+                println!(
+                    "{} {}",
+                    "📋 Systemd Status".magenta().bold(),
+                    self.name.cyan().bold()
+                );
+            }
+            status::print_orphaned_units(&orphans, &mut counts);
+        }
+
+        println!(
+            "\nStatus: {} ok, {} drifted",
+            counts.ok.to_string().green(),
+            counts.drifted.to_string().red()
+        );
+        counts.drifted == 0
+    }
+
+    /// Stop and disable units owl enabled or started that the graph no longer
+    /// declares, and remove their unit files unless something still declares them.
+    fn prune_units(&self) -> bool {
+        let mut declared_targets = HashSet::new();
+        self.for_each_declared(|s, skip| {
+            declared_targets.extend(s.declared_targets(skip));
+        });
+        let mut state = get_state();
+        let orphans = self.orphaned_units(&state);
+
+        println!(
+            "{} {}",
+            "🧹 Prune Units".magenta().bold(),
+            self.name.cyan().bold()
+        );
+        if orphans.is_empty() {
+            println!("  {}", "Nothing to prune".green());
+            return true;
+        }
+        print_subsection("Orphaned Units");
+        let mut ok = true;
+        let mut reload = Vec::new();
+        for record in orphans {
+            let scope = if record.root {
+                ServiceScope::System
+            } else {
+                ServiceScope::User
+            };
+            let mut actions = Vec::new();
+            if record.started {
+                actions.push("stop");
+            }
+            if record.enabled {
+                actions.push("disable");
+            }
+            if let Err(e) = actions.iter().try_for_each(|a| scope.run(a, &record.unit)) {
+                println!("    {} ❌ {}", record.unit.green(), e);
+                ok = false;
+                continue;
+            }
+            let link = state.find_link(&record.file).cloned();
+            if let Some(link) = link.filter(|_| !declared_targets.contains(&record.file)) {
+                match unlink_record(&link) {
+                    Ok(()) => {
+                        state.forget_link(&link.target);
+                        actions.push("unlink");
+                        if !reload.contains(&scope) {
+                            reload.push(scope);
+                        }
+                    }
+                    Err(e) if e.keeps_record() => {
+                        println!(
+                            "    {} ❌ {}",
+                            link.target.display().to_string().green(),
+                            e.message()
+                        );
+                        ok = false;
+                        continue;
+                    }
+                    Err(e) => println!(
+                        "    {} ⚠️  {}",
+                        link.target.display().to_string().green(),
+                        e.message()
+                    ),
+                }
+            }
+            state
+                .units
+                .retain(|u| !(u.unit == record.unit && u.root == record.root));
+            println!(
+                "    {} {} ✅ {}",
+                record.unit.green(),
+                format!("({})", record.setup).cyan(),
+                actions.join(", ").dimmed()
+            );
+        }
+        save_state(&state);
+        for scope in reload {
+            let _ = scope.systemctl().arg("daemon-reload").status();
+        }
+        ok
+    }
+
+    /// Remove links owl owns that no setup in this graph declares anymore.
+    /// Returns false if any orphan could not be removed.
+    fn prune(&self) -> bool {
+        let mut declared = HashSet::new();
+        self.for_each_declared(|s, skip| {
+            declared.extend(s.declared_targets(skip));
+        });
+
+        let mut state = get_state();
+        let orphans: Vec<LinkRecord> = state
+            .links
+            .iter()
+            .filter(|l| !declared.contains(&l.target))
+            .cloned()
+            .collect();
+
+        println!(
+            "{} {}",
+            "🧹 Prune".magenta().bold(),
+            self.name.cyan().bold()
+        );
+        if orphans.is_empty() {
+            println!("  {}", "Nothing to prune".green());
+            return true;
+        }
+        print_subsection("Orphaned Links");
+        let mut ok = true;
+        for record in orphans {
+            let result = unlink_record(&record);
+            if result.as_ref().is_err_and(UnlinkError::keeps_record) {
+                ok = false;
+            } else {
+                state.forget_link(&record.target);
+            }
+            print_unlink_result(&record, result);
+        }
+        save_state(&state);
+        ok
     }
 }
 
@@ -733,11 +2029,11 @@ enum SetupLoadError {
         #[source]
         source: std::io::Error,
     },
-    #[error("Invalid JSON in {path}: {source}")]
-    Json {
+    #[error("Invalid {} in {path}: {message}", .format.name())]
+    Parse {
         path: PathBuf,
-        #[source]
-        source: serde_json::Error,
+        format: setup_file::Format,
+        message: String,
     },
     #[error("Validation error in {path}: {message}")]
     Validation { path: PathBuf, message: String },
@@ -755,10 +2051,15 @@ fn load_setup_by_path(setup_path: &Path) -> Result<Setup, SetupLoadError> {
         source: e,
     })?;
 
-    let raw: SetupFileRaw = serde_json::from_str(&setup_raw).map_err(|e| SetupLoadError::Json {
-        path: setup_path.to_path_buf(),
-        source: e,
-    })?;
+    let raw: SetupFileRaw =
+        setup_header
+            .format
+            .parse(&setup_raw)
+            .map_err(|message| SetupLoadError::Parse {
+                path: setup_path.to_path_buf(),
+                format: setup_header.format,
+                message,
+            })?;
 
     let setup = Setup::make(&raw, &setup_header).map_err(|e| SetupLoadError::Validation {
         path: setup_path.to_path_buf(),
@@ -776,11 +2077,17 @@ enum SetupLoadByNameError {
     },
 }
 
+/// The setup file in `dir`, or where setup.json would be so loading it
+/// reports the missing file.
+fn setup_file_in(dir: &Path) -> PathBuf {
+    setup_file::find(dir).unwrap_or_else(|| dir.join("setup.json"))
+}
+
 fn load_setup_by_name(name: &str) -> Result<Setup, SetupLoadByNameError> {
     let config = get_config();
 
-    let nest_dir = config.owl_path.join("nests").join(name).join("setup.json");
-    let setup_dir = config.owl_path.join("setups").join(name).join("setup.json");
+    let nest_dir = setup_file_in(&config.owl_path.join("nests").join(name));
+    let setup_dir = setup_file_in(&config.owl_path.join("setups").join(name));
 
     let nest_dir_setup = load_setup_by_path(&nest_dir);
     let setup_dir_setup = load_setup_by_path(&setup_dir);
@@ -796,6 +2103,16 @@ fn load_setup_by_name(name: &str) -> Result<Setup, SetupLoadByNameError> {
     }
 }
 
+fn require_setup_name(name: Option<String>) -> String {
+    name.unwrap_or_else(|| {
+        eprintln!(
+            "{} missing setup name: owl setup <name> <command>",
+            "Error:".red()
+        );
+        std::process::exit(1);
+    })
+}
+
 fn get_setup(name: &str) -> Setup {
     match load_setup_by_name(name) {
         Ok(s) => s,
@@ -806,38 +2123,99 @@ fn get_setup(name: &str) -> Setup {
     }
 }
 
-fn validate_all_setups() {
+fn validate_all_setups(as_json: bool, strict: bool) {
     let config = get_config();
     let setups_dir = config.owl_path.join("setups");
     let nests_dir = config.owl_path.join("nests");
 
     let setups_headers = read_setup_headers_from_dir(&setups_dir);
     let nests_headers = read_setup_headers_from_dir(&nests_dir);
+    let nest_names: Vec<String> = nests_headers.iter().map(|h| h.name.clone()).collect();
     let all_headers = [setups_headers, nests_headers].concat();
 
+    let mut results = Vec::new();
+    for header in all_headers {
+        let setup = load_setup_by_path(&header.setup_file_path);
+        let mut lints = Vec::new();
+        if strict {
+            lints.extend(lint::lint_file(&header));
+            if let Ok(setup) = &setup {
+                lints.extend(lint::lint_setup(setup));
+            }
+        }
+        results.push(json::ValidatedDoc {
+            error: setup.err().map(|e| e.to_string()),
+            lints,
+            name: header.name,
+            file: header.setup_file_path,
+        });
+    }
+
+    let mut graphs = Vec::new();
+    if strict {
+        for nest in nest_names {
+            // Setups that fail to load are already reported above
+            let setups: Vec<Setup> = graph::get_dependency_graph()
+                .reachable(&nest)
+                .iter()
+                .filter_map(|name| load_setup_by_name(name).ok())
+                .collect();
+            graphs.push(json::GraphLintsDoc {
+                lints: lint::lint_graph(&setups),
+                nest,
+            });
+        }
+    }
+
+    let failed = results.iter().any(|r| r.failed())
+        || graphs.iter().flat_map(|g| &g.lints).any(|l| l.is_error());
+    if as_json {
+        json::print(&json::validate(strict, results, graphs));
+    } else {
+        print_validation(&results, &graphs);
+    }
+    if strict && failed {
+        std::process::exit(1);
+    }
+}
+
+fn print_validation(results: &[json::ValidatedDoc], graphs: &[json::GraphLintsDoc]) {
     let mut total_ok = 0usize;
     let mut total_err = 0usize;
+    let mut warnings = 0usize;
+
+    for result in results {
+        match &result.error {
+            Some(e) => println!("{} {} {}", "✗".red(), result.name.red(), e),
+            None if result.failed() => println!("{} {}", "✗".red(), result.name.red()),
+            None => println!("{} {}", "✓".green(), result.name.green()),
+        }
+        if result.failed() {
+            total_err += 1;
+        } else {
+            total_ok += 1;
+        }
+        for lint in &result.lints {
+            lint.print();
+        }
+        warnings += result.lints.iter().filter(|l| !l.is_error()).count();
+    }
 
-    for header in all_headers {
-        let setup = load_setup_by_path(&header.setup_file_path);
-        match setup {
-            Ok(_) => {
-                println!("{} {}", "✓".green(), header.name.green());
-                total_ok += 1;
-            }
-            Err(e) => {
-                println!("{} {} {}", "✗".red(), header.name.red(), e);
-                total_err += 1;
-            }
+    for graph in graphs.iter().filter(|g| !g.lints.is_empty()) {
+        println!("\n{} {}", "Nest graph".bold(), graph.nest.cyan().bold());
+        for lint in &graph.lints {
+            lint.print();
         }
+        warnings += graph.lints.iter().filter(|l| !l.is_error()).count();
     }
 
     if total_ok + total_err > 0 {
         println!(
-            "\nValidated total {}: {} ok, {} failed",
+            "\nValidated total {}: {} ok, {} failed, {} warnings",
             (total_ok + total_err).to_string().bold(),
             total_ok.to_string().green(),
-            total_err.to_string().red()
+            total_err.to_string().red(),
+            warnings.to_string().yellow()
         );
     }
 }
@@ -848,7 +2226,7 @@ fn validate_all_setups() {
 
 fn get_nest_path() -> Option<PathBuf> {
     let config = get_config();
-    config.nest_path.clone().map(|p| p.join("setup.json"))
+    config.nest_path.as_deref().map(setup_file_in)
 }
 
 fn load_nest() -> Result<Setup, SetupLoadError> {
@@ -861,7 +2239,7 @@ fn load_nest() -> Result<Setup, SetupLoadError> {
             });
         }
     };
-    return load_setup_by_path(&nest_path);
+    load_setup_by_path(&nest_path)
 }
 
 fn get_nest() -> Setup {
@@ -869,7 +2247,7 @@ fn get_nest() -> Setup {
         Ok(s) => s,
         Err(e) => {
             eprintln!("No active nest found! {}", e.to_string().red());
-            return switch_nest();
+            switch_nest()
         }
     }
 }
@@ -906,7 +2284,7 @@ fn switch_nest() -> Setup {
     save_config(config);
     println!("Switched nest to {}", setup_header.name.cyan());
 
-    return get_setup(&setup_header.name);
+    get_setup(&setup_header.name)
 }
 
 fn list_nests() -> Vec<SetupHeader> {
@@ -921,6 +2299,9 @@ fn list_nests() -> Vec<SetupHeader> {
 #[derive(Parser)]
 #[command(author, version, about = "Modular dotfiles and environment management CLI", long_about = None, arg_required_else_help = true)]
 struct Cli {
+    /// Print JSON instead of text (config, info, status and setups-validate)
+    #[arg(long, global = true, default_value_t = false)]
+    json: bool,
     #[command(subcommand)]
     command: Commands,
 }
@@ -937,6 +2318,9 @@ enum Commands {
         /// Only apply to the nest itself, skip dependencies
         #[arg(long, default_value_t = false)]
         shallow: bool,
+        /// Keep running setups whose dependencies failed
+        #[arg(long, default_value_t = false)]
+        keep_going: bool,
     },
 
     /// Sync owl repository (fetch, merge, optionally push)
@@ -944,18 +2328,25 @@ enum Commands {
 
     /// Run operations on a specific setup
     Setup {
-        /// Name of the setup to operate on
-        setup_name: String,
+        /// Name of the setup to operate on (not used by `new`)
+        setup_name: Option<String>,
         #[command(subcommand)]
         setup_command: SetupCommands,
         /// Only apply to this setup, skip dependencies
         #[arg(long, default_value_t = false)]
         shallow: bool,
+        /// Keep running setups whose dependencies failed
+        #[arg(long, default_value_t = false)]
+        keep_going: bool,
     },
 
-    /// Validate all setup.json files
+    /// Validate all setup files
     #[command(name = "setups-validate")]
-    SetupsValidate,
+    SetupsValidate {
+        /// Also lint unknown fields, missing link sources and collisions within each nest's graph
+        #[arg(long, default_value_t = false)]
+        strict: bool,
+    },
 
     /// Update owl binary from GitHub releases
     Update {
@@ -966,61 +2357,346 @@ enum Commands {
 
     /// Pull latest changes and rebuild owl from source
     Upgrade,
+
+    /// Print the dependency graph of the active nest (or a given setup)
+    Graph {
+        /// Setup or nest to start from (defaults to the active nest)
+        name: Option<String>,
+        /// Print an indented tree (default)
+        #[arg(long, default_value_t = false, conflicts_with = "dot")]
+        tree: bool,
+        /// Print Graphviz DOT
+        #[arg(long, default_value_t = false)]
+        dot: bool,
+    },
+
+    /// Restore the most recent backup of a file owl replaced with a link
+    Restore {
+        /// Path the backup was taken from (e.g. ~/.bashrc)
+        target: String,
+    },
+
+    /// Inspect files owl backed up before linking over them
+    Backups {
+        #[command(subcommand)]
+        backups_command: BackupsCommands,
+    },
+
+    /// Work with the rc scripts of the active nest
+    Rc {
+        #[command(subcommand)]
+        rc_command: RcCommands,
+    },
+
+    /// Print the JSON Schema for setup.json
+    Schema {
+        /// Write it to the repo and add `$schema` to every setup file instead
+        #[arg(long, default_value_t = false)]
+        write: bool,
+    },
+
+    /// Move a live file into a setup, add it to the setup's links and link it
+    Adopt {
+        /// The file or directory to adopt, e.g. ~/.config/foo/bar.conf
+        path: String,
+        /// The setup to adopt it into
+        #[arg(long)]
+        setup: String,
+        /// Move and link it with sudo; needed for files outside $HOME such as /etc
+        #[arg(long, default_value_t = false)]
+        root: bool,
+    },
 }
 
-#[derive(Subcommand, Clone, Copy)]
+#[derive(Subcommand, Clone)]
 enum SetupCommands {
     /// Create symlinks for configs, rc scripts, menu scripts, and services
     Link,
+    /// Remove the symlinks owl created for this setup
+    Unlink,
     /// Show what operations would be performed (dry run)
     Info,
-    /// Open setup.json in your editor
+    /// Check declared links and services against the system (exits non-zero on drift)
+    Status,
+    /// Open the setup file in your editor
     Edit,
+    /// Create a new setup under setups/ (`owl setup new <name>`)
+    New {
+        /// Name of the setup directory
+        name: String,
+        #[command(flatten)]
+        scaffold: ScaffoldArgs,
+        /// Add an install.sh skeleton
+        #[arg(long, default_value_t = false)]
+        install: bool,
+        /// Add an rc.sh skeleton
+        #[arg(long, default_value_t = false)]
+        rc: bool,
+        /// Add a user systemd service skeleton
+        #[arg(long, default_value_t = false)]
+        service: bool,
+        /// Add it to the active nest's dependencies
+        #[arg(long, default_value_t = false)]
+        add_to_nest: bool,
+    },
+    /// Rewrite the setup file as JSON, TOML or YAML
+    Convert {
+        /// json, toml or yaml
+        #[arg(long, value_name = "FORMAT")]
+        to: String,
+    },
     /// Run the setup's install script
-    Install,
+    Install {
+        #[command(flatten)]
+        install: InstallArgs,
+        /// Run up to N install scripts at once, as their dependencies finish
+        #[arg(short = 'j', long, default_value_t = 1, value_name = "N")]
+        jobs: usize,
+    },
     /// Link and enable systemd services
     Systemd,
     /// Run link, install, and systemd operations
-    All,
+    All(InstallArgs),
+}
+
+#[derive(clap::Args, Clone)]
+struct ScaffoldArgs {
+    /// Format of the new setup file: json, toml or yaml
+    #[arg(long, value_name = "FORMAT", default_value = "json")]
+    format: String,
+}
+
+impl ScaffoldArgs {
+    fn format(&self) -> setup_file::Format {
+        setup_file::Format::from_name(&self.format).unwrap_or_else(|e| {
+            eprintln!("{}", e.red());
+            std::process::exit(1);
+        })
+    }
+}
+
+#[derive(clap::Args, Clone, Default)]
+struct InstallArgs {
+    /// Re-run install scripts even if they are up to date
+    #[arg(long, default_value_t = false)]
+    force: bool,
+    /// Only run the install script of this setup
+    #[arg(long, value_name = "SETUP")]
+    only: Option<String>,
+}
+
+/// Flags shared by every operation that walks a setup graph.
+#[derive(Default)]
+struct RunOptions {
+    shallow: bool,
+    keep_going: bool,
+    /// Install scripts to run concurrently; 0 or 1 runs them one at a time
+    jobs: usize,
+    install: InstallArgs,
+}
+
+#[derive(Subcommand)]
+enum BackupsCommands {
+    /// List all backups, newest first
+    List,
+}
+
+#[derive(Subcommand)]
+enum RcCommands {
+    /// Regenerate the environment, aliases, shell loaders and rc bundles without linking
+    Generate,
+    /// Time each rc script in a subshell and list the slowest
+    Profile {
+        /// Shell to profile: bash, zsh, fish or nu (defaults to $SHELL)
+        #[arg(long)]
+        shell: Option<String>,
+        /// Runs per script; the fastest is reported
+        #[arg(long, default_value_t = 3)]
+        runs: usize,
+        /// Number of scripts to list
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum SystemdCommands {
+    /// Show whether each declared unit is enabled and active
+    Status,
 }
 
 #[derive(Subcommand)]
 enum NestCommands {
     /// Create symlinks for configs, rc scripts, menu scripts, and services
     Link,
+    /// Remove the symlinks owl created for the nest
+    Unlink,
+    /// Remove owl-created symlinks that the nest no longer declares
+    Prune,
     /// Show what operations would be performed (dry run)
     Info,
-    /// Open nest's setup.json in your editor
+    /// Check declared links and services against the system (exits non-zero on drift)
+    Status,
+    /// Open the nest's setup file in your editor
     Edit,
     /// Run install scripts for nest and dependencies
-    Install,
+    Install {
+        #[command(flatten)]
+        install: InstallArgs,
+        /// Run up to N install scripts at once, as their dependencies finish
+        #[arg(short = 'j', long, default_value_t = 1, value_name = "N")]
+        jobs: usize,
+    },
     /// Link and enable systemd services
-    Systemd,
+    #[command(args_conflicts_with_subcommands = true)]
+    Systemd {
+        /// Also stop, disable and unlink units owl enabled that the nest no longer declares
+        #[arg(long)]
+        prune: bool,
+        #[command(subcommand)]
+        systemd_command: Option<SystemdCommands>,
+    },
+    /// Render secret templates through their providers
+    Secrets,
     /// Run link, install, and systemd operations
-    All,
+    All(InstallArgs),
     /// Switch to a different nest
     Switch,
+    /// Create a new nest under nests/, empty or as a copy of another
+    New {
+        /// Name of the nest directory
+        name: String,
+        /// Copy this nest instead of starting empty
+        #[arg(long, value_name = "NEST", conflicts_with = "format")]
+        from: Option<String>,
+        #[command(flatten)]
+        scaffold: ScaffoldArgs,
+    },
+}
+
+/// `--json` variants of the commands that support it.
+fn run_json_command(command: Commands) {
+    let (setup, command_is_info, shallow) = match command {
+        Commands::Config => return json::print(&json::config()),
+        Commands::SetupsValidate { strict } => return validate_all_setups(true, strict),
+        // Already JSON
+        Commands::Schema { write: false } => return schema::print(),
+        Commands::Nest {
+            nest_command: None | Some(NestCommands::Info),
+            shallow,
+            ..
+        } => (load_nest().map_err(|e| e.to_string()), true, shallow),
+        Commands::Nest {
+            nest_command: Some(NestCommands::Status),
+            shallow,
+            ..
+        } => (load_nest().map_err(|e| e.to_string()), false, shallow),
+        Commands::Setup {
+            setup_name,
+            setup_command: command @ (SetupCommands::Info | SetupCommands::Status),
+            shallow,
+            ..
+        } => (
+            load_setup_by_name(&require_setup_name(setup_name)).map_err(|e| e.to_string()),
+            matches!(command, SetupCommands::Info),
+            shallow,
+        ),
+        _ => {
+            eprintln!(
+                "{} --json is only supported by config, info, status, setups-validate and schema",
+                "Error:".red()
+            );
+            std::process::exit(1);
+        }
+    };
+    let setup = match setup {
+        Ok(setup) => setup,
+        Err(e) => {
+            eprintln!("{} {}", "Error loading setup:".red(), e);
+            std::process::exit(1);
+        }
+    };
+    if command_is_info {
+        json::print(&json::info(&setup, shallow));
+    } else {
+        let status = json::status(&setup, shallow);
+        json::print(&status);
+        if !status.is_clean() {
+            std::process::exit(1);
+        }
+    }
 }
 
 fn main() {
     let cli = Cli::parse();
+    if cli.json {
+        return run_json_command(cli.command);
+    }
     match cli.command {
         Commands::Config => print_config(),
         Commands::Nest {
             nest_command,
             shallow,
+            keep_going,
         } => {
+            if let Some(NestCommands::New {
+                name,
+                from,
+                scaffold,
+            }) = &nest_command
+            {
+                if let Err(e) = scaffold::new_nest(name, from.as_deref(), scaffold.format()) {
+                    eprintln!("{} {}", "Unable to create nest:".red(), e);
+                    std::process::exit(1);
+                }
+                return;
+            }
             let nest = get_nest();
-            match nest_command {
-                None | Some(NestCommands::Info) => nest.run_op(Operation::Info, shallow),
-                Some(NestCommands::Link) => nest.run_op(Operation::Link, shallow),
-                Some(NestCommands::Install) => nest.run_op(Operation::Install, shallow),
-                Some(NestCommands::Systemd) => nest.run_op(Operation::Systemd, shallow),
-                Some(NestCommands::All) => nest.run_op(Operation::All, shallow),
-                Some(NestCommands::Edit) => nest.edit(),
+            let opts = RunOptions {
+                shallow,
+                keep_going,
+                ..Default::default()
+            };
+            let ok = match nest_command {
+                None | Some(NestCommands::Info) => nest.run_op(Operation::Info, &opts),
+                Some(NestCommands::Link) => nest.run_op(Operation::Link, &opts),
+                Some(NestCommands::Unlink) => nest.run_op(Operation::Unlink, &opts),
+                Some(NestCommands::Prune) => nest.prune(),
+                Some(NestCommands::Status) => nest.status(shallow),
+                Some(NestCommands::Install { install, jobs }) => nest.run_op(
+                    Operation::Install,
+                    &RunOptions {
+                        install,
+                        jobs,
+                        ..opts
+                    },
+                ),
+                Some(NestCommands::Systemd {
+                    systemd_command: Some(SystemdCommands::Status),
+                    ..
+                }) => nest.systemd_status(shallow),
+                Some(NestCommands::Systemd { prune, .. }) => {
+                    let ok = nest.run_op(Operation::Systemd, &opts);
+                    // Prune even after a failure; orphans do not depend on what failed
+                    let pruned = !prune || nest.prune_units();
+                    ok && pruned
+                }
+                Some(NestCommands::Secrets) => nest.run_op(Operation::Secrets, &opts),
+                Some(NestCommands::All(install)) => {
+                    nest.run_op(Operation::All, &RunOptions { install, ..opts })
+                }
+                Some(NestCommands::Edit) => {
+                    nest.edit();
+                    true
+                }
                 Some(NestCommands::Switch) => {
                     let _ = switch_nest();
+                    true
                 }
+                Some(NestCommands::New { .. }) => unreachable!("handled before loading the nest"),
+            };
+            if !ok {
+                std::process::exit(1);
             }
         }
         Commands::Sync => sync(),
@@ -1028,21 +2704,160 @@ fn main() {
             setup_name,
             setup_command,
             shallow,
+            keep_going,
         } => {
-            let s = get_setup(&setup_name);
-
-            match setup_command {
-                SetupCommands::Link => s.run_op(Operation::Link, shallow),
-                SetupCommands::Info => s.run_op(Operation::Info, shallow),
-                SetupCommands::Edit => s.edit(),
-                SetupCommands::Install => s.run_op(Operation::Install, shallow),
-                SetupCommands::Systemd => s.run_op(Operation::Systemd, shallow),
-                SetupCommands::All => s.run_op(Operation::All, shallow),
+            if let SetupCommands::New {
+                name,
+                scaffold,
+                install,
+                rc,
+                service,
+                add_to_nest,
+            } = setup_command
+            {
+                if setup_name.is_some() {
+                    eprintln!("{} use `owl setup new <name>`", "Error:".red());
+                    std::process::exit(1);
+                }
+                let options = scaffold::NewSetupOptions {
+                    install,
+                    rc,
+                    service,
+                    add_to_nest,
+                    format: scaffold.format(),
+                };
+                if let Err(e) = scaffold::new_setup(&name, &options) {
+                    eprintln!("{} {}", "Unable to create setup:".red(), e);
+                    std::process::exit(1);
+                }
+                return;
+            }
+            let s = get_setup(&require_setup_name(setup_name));
+            let opts = RunOptions {
+                shallow,
+                keep_going,
+                ..Default::default()
+            };
+
+            let ok = match setup_command {
+                SetupCommands::Link => s.run_op(Operation::Link, &opts),
+                SetupCommands::Unlink => s.run_op(Operation::Unlink, &opts),
+                SetupCommands::Info => s.run_op(Operation::Info, &opts),
+                SetupCommands::Status => s.status(shallow),
+                SetupCommands::Edit => {
+                    s.edit();
+                    true
+                }
+                SetupCommands::New { .. } => unreachable!("handled before loading the setup"),
+                SetupCommands::Convert { to } => {
+                    let format = setup_file::Format::from_name(&to).unwrap_or_else(|e| {
+                        eprintln!("{}", e.red());
+                        std::process::exit(1);
+                    });
+                    s.convert(format)
+                }
+                SetupCommands::Install { install, jobs } => s.run_op(
+                    Operation::Install,
+                    &RunOptions {
+                        install,
+                        jobs,
+                        ..opts
+                    },
+                ),
+                SetupCommands::Systemd => s.run_op(Operation::Systemd, &opts),
+                SetupCommands::All(install) => {
+                    s.run_op(Operation::All, &RunOptions { install, ..opts })
+                }
+            };
+            if !ok {
+                std::process::exit(1);
             }
         }
-        Commands::SetupsValidate => validate_all_setups(),
+        Commands::SetupsValidate { strict } => validate_all_setups(false, strict),
         Commands::Update { recursive } => run_update(recursive),
         Commands::Upgrade => run_upgrade(),
+        Commands::Graph { name, tree: _, dot } => print_graph(name, dot),
+        Commands::Restore { target } => backup::restore(&target),
+        Commands::Backups { backups_command } => match backups_command {
+            BackupsCommands::List => backup::print_backups(),
+        },
+        Commands::Rc { rc_command } => run_rc_command(rc_command),
+        Commands::Schema { write: false } => schema::print(),
+        Commands::Schema { write: true } => schema::write(),
+        Commands::Adopt { path, setup, root } => {
+            if let Err(e) = adopt::adopt(&path, &setup, root) {
+                eprintln!("{} {}", "Unable to adopt:".red(), e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+fn run_rc_command(rc_command: RcCommands) {
+    let nest = get_nest();
+    match rc_command {
+        RcCommands::Generate => {
+            let mut report = RunReport::default();
+            nest.generate_shell_files(&mut report);
+            if !report.is_success() {
+                report.print_summary();
+                std::process::exit(1);
+            }
+        }
+        RcCommands::Profile { shell, runs, top } => {
+            let shell = match shell {
+                Some(name) => shell::Shell::from_name(&name).unwrap_or_else(|e| {
+                    eprintln!("{}", e.red());
+                    std::process::exit(1);
+                }),
+                // Fall back to the login shell, then bash
+                None => std::env::var("SHELL")
+                    .ok()
+                    .and_then(|s| {
+                        let name = Path::new(&s).file_name()?.to_string_lossy().to_string();
+                        shell::Shell::from_name(&name).ok()
+                    })
+                    .unwrap_or(shell::Shell::Bash),
+            };
+            if let Err(e) = rc::profile(&nest.graph_setups(), shell, runs, top) {
+                eprintln!("{} {}", "Error profiling rc scripts:".red(), e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+fn print_graph(name: Option<String>, dot: bool) {
+    // Resolve the active nest by name only; loading it would fail on the very cycles we report
+    let active_nest_name = || {
+        get_config()
+            .nest_path
+            .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+    };
+    let root = match name.or_else(active_nest_name) {
+        Some(n) => n,
+        None => {
+            eprintln!("{}", "No active nest found! Run `owl nest switch`".red());
+            std::process::exit(1);
+        }
+    };
+    let graph = graph::get_dependency_graph();
+    if !graph.contains(&root) {
+        eprintln!("{} {}", "Setup not found:".red(), root.yellow());
+        std::process::exit(1);
+    }
+    if let Some(cycle) = graph.find_cycle(&root) {
+        eprintln!(
+            "{} {}",
+            "Dependency cycle:".red(),
+            graph::format_cycle(&cycle).yellow()
+        );
+        std::process::exit(1);
+    }
+    if dot {
+        graph.print_dot(&root);
+    } else {
+        graph.print_tree(&root);
     }
 }
 
@@ -1177,8 +2992,18 @@ fn sync() {
 
 fn run_update(recursive: bool) {
     let s = get_setup("owl");
-    let shallow = !recursive;
-    s.run_op(Operation::Install, shallow);
+    let opts = RunOptions {
+        shallow: !recursive,
+        // Updating is the whole point, so never skip the install script as up to date
+        install: InstallArgs {
+            force: true,
+            only: None,
+        },
+        ..Default::default()
+    };
+    if !s.run_op(Operation::Install, &opts) {
+        std::process::exit(1);
+    }
 }
 
 fn run_upgrade() {
@@ -1232,7 +3057,13 @@ fn run_upgrade() {
 
     // Step 3: Link binaries using the owl setup
     let owl_setup = get_setup("owl");
-    owl_setup.run_op(Operation::Link, true);
+    let opts = RunOptions {
+        shallow: true,
+        ..Default::default()
+    };
+    if !owl_setup.run_op(Operation::Link, &opts) {
+        std::process::exit(1);
+    }
 
     println!("{}", "Upgrade complete!".green().bold());
 }
@@ -1249,63 +3080,95 @@ fn print_subsection(title: &str) {
     println!("  {}", title.green().bold());
 }
 
-fn print_kv(label: &str, value: &str) {
-    println!("  {} {}", format!("{}:", label).white(), value.cyan());
+/// The `[prefix] ` put in front of every line a setup prints while others run alongside it.
+fn output_tag(prefix: Option<&str>) -> String {
+    prefix
+        .map(|p| format!("[{}] ", p).cyan().to_string())
+        .unwrap_or_default()
 }
 
-fn run_script(script_path: &Path) {
-    let display_path = script_path.display().to_string();
-    if !script_path.exists() {
-        eprintln!("Script not found, skipping: {}", display_path);
-        return;
-    }
-
-    println!("Running script: {}", display_path);
-
-    let mut cmd = Command::new("bash");
-    cmd.arg(&script_path);
-
+/// Run `cmd`, streaming its stdout and stderr line by line with `tag` in front.
+fn run_tagged(cmd: &mut Command, tag: &str) -> std::io::Result<std::process::ExitStatus> {
     let mut child = cmd
         .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped())
-        .spawn()
-        .expect("Failed to spawn command");
+        .spawn()?;
 
     // Read and print stdout/stderr concurrently to avoid deadlocks
-    let stdout_handle = if let Some(stdout) = child.stdout.take() {
-        Some(std::thread::spawn(move || {
+    let stdout_handle = child.stdout.take().map(|stdout| {
+        let tag = tag.to_string();
+        std::thread::spawn(move || {
             let stdout_reader = BufReader::new(stdout);
-            for line in stdout_reader.lines().flatten() {
-                println!("{}", line);
+            for line in stdout_reader.lines().map_while(Result::ok) {
+                println!("{}{}", tag, line);
             }
-        }))
-    } else {
-        None
-    };
+        })
+    });
 
-    let stderr_handle = if let Some(stderr) = child.stderr.take() {
-        Some(std::thread::spawn(move || {
+    let stderr_handle = child.stderr.take().map(|stderr| {
+        let tag = tag.to_string();
+        std::thread::spawn(move || {
             let stderr_reader = BufReader::new(stderr);
-            for line in stderr_reader.lines().flatten() {
-                eprintln!("{}", line);
+            for line in stderr_reader.lines().map_while(Result::ok) {
+                eprintln!("{}{}", tag, line);
             }
-        }))
-    } else {
-        None
-    };
+        })
+    });
 
-    // Wait for the command to finish and check the status
-    let status = child.wait().expect("Failed to wait on child process");
+    let status = child.wait();
     if let Some(h) = stdout_handle {
         let _ = h.join();
     }
     if let Some(h) = stderr_handle {
         let _ = h.join();
     }
+    status
+}
+
+fn print_kv(label: &str, value: &str) {
+    println!("  {} {}", format!("{}:", label).white(), value.cyan());
+}
+
+/// Run a bash script, streaming its output. With a `prefix`, every line is tagged
+/// `[prefix]` so output from scripts running side by side stays attributable.
+fn run_script(script_path: &Path, prefix: Option<&str>) -> Result<(), String> {
+    let display_path = script_path.display().to_string();
+    let tag = output_tag(prefix);
+    if !script_path.exists() {
+        eprintln!("{}Script not found, skipping: {}", tag, display_path);
+        return Err(format!("script not found: {}", display_path));
+    }
+
+    println!("{}Running script: {}", tag, display_path);
+
+    let mut cmd = Command::new("bash");
+    cmd.arg(script_path);
+    let status = run_tagged(&mut cmd, &tag).map_err(|e| format!("exec bash: {}", e))?;
     if !status.success() {
-        eprintln!("Command failed with exit code: {:?}", status.code());
+        eprintln!("{}Command failed with exit code: {:?}", tag, status.code());
+        Err(match status.code() {
+            Some(code) => format!("exit code {}", code),
+            None => "terminated by signal".to_string(),
+        })
     } else {
-        println!("Script completed successfully");
+        println!("{}Script completed successfully", tag);
+        Ok(())
+    }
+}
+
+fn get_state() -> OwlState {
+    match OwlState::load() {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("{} {}", "Error loading owl state:".red(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn save_state(state: &OwlState) {
+    if let Err(e) = state.save() {
+        eprintln!("{} {}", "Error saving owl state:".red(), e);
     }
 }
 
@@ -1313,15 +3176,254 @@ struct LinkingError {
     message: String,
 }
 
+fn remove_target_file(target_path: &Path, root: bool) -> Result<(), LinkingError> {
+    if root {
+        let output = Command::new("sudo").arg("rm").arg(target_path).output();
+        match output {
+            Ok(o) if o.status.success() => Ok(()),
+            Ok(o) => Err(LinkingError {
+                message: format!("sudo rm failed: {}", String::from_utf8_lossy(&o.stderr)),
+            }),
+            Err(e) => Err(LinkingError {
+                message: format!("exec sudo rm: {}", e),
+            }),
+        }
+    } else {
+        std::fs::remove_file(target_path).map_err(|e| LinkingError {
+            message: format!("remove: {}", e),
+        })
+    }
+}
+
+/// Why `unlink_record` did not remove a link.
+enum UnlinkError {
+    /// The target changed since owl created it, so it is no longer owl's to remove
+    LeftAlone(String),
+    /// Removing it failed (e.g. sudo was denied); owl still owns the link
+    Failed(LinkingError),
+}
+
+impl UnlinkError {
+    fn message(&self) -> &str {
+        match self {
+            UnlinkError::LeftAlone(message) => message,
+            UnlinkError::Failed(e) => &e.message,
+        }
+    }
+
+    /// Whether the state should keep the record so a later unlink or prune can retry.
+    fn keeps_record(&self) -> bool {
+        matches!(self, UnlinkError::Failed(_))
+    }
+}
+
+/// Remove a link owl created, but only if it is still exactly what owl left there.
+fn unlink_record(record: &LinkRecord) -> Result<(), UnlinkError> {
+    let target_path = &record.target;
+    if target_path.symlink_metadata().is_err() {
+        return Ok(());
+    }
+
+    if record.content_hash.is_some() {
+        let unchanged = !target_path.is_symlink()
+            && state::file_hash(target_path).as_ref() == record.content_hash.as_ref();
+        if !unchanged {
+            return Err(UnlinkError::LeftAlone(
+                "file was modified since owl wrote it, leaving it alone".to_string(),
+            ));
+        }
+        return remove_target_file(target_path, record.root).map_err(UnlinkError::Failed);
+    }
+
+    if record.mode == LinkMode::Hardlink {
+        if !is_same_file(target_path, &record.source) {
+            return Err(UnlinkError::LeftAlone(
+                "target is no longer hardlinked to the source, leaving it alone".to_string(),
+            ));
+        }
+        return remove_target_file(target_path, record.root).map_err(UnlinkError::Failed);
+    }
+
+    if !target_path.is_symlink() {
+        return Err(UnlinkError::LeftAlone(
+            "target is no longer a symlink, leaving it alone".to_string(),
+        ));
+    }
+    match std::fs::read_link(target_path) {
+        Ok(dest) if dest == record.source => {}
+        Ok(dest) => {
+            return Err(UnlinkError::LeftAlone(format!(
+                "target now points to {}, leaving it alone",
+                dest.display()
+            )));
+        }
+        Err(e) => {
+            return Err(UnlinkError::Failed(LinkingError {
+                message: format!("read link: {}", e),
+            }));
+        }
+    }
+    remove_target_file(target_path, record.root).map_err(UnlinkError::Failed)
+}
+
+/// True when both paths are the same inode (i.e. hardlinks of each other).
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (std::fs::symlink_metadata(a), std::fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+/// Write `contents` to `target_path`, keeping the permission bits of `source_path`.
+fn write_target_file(
+    source_path: &Path,
+    target_path: &Path,
+    contents: &[u8],
+    root: bool,
+) -> Result<(), LinkingError> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = std::fs::metadata(source_path)
+        .map(|m| m.permissions().mode() & 0o7777)
+        .unwrap_or(0o644);
+
+    if !root {
+        fs::write(target_path, contents).map_err(|e| LinkingError {
+            message: format!("write: {}", e),
+        })?;
+        return fs::set_permissions(target_path, fs::Permissions::from_mode(mode)).map_err(|e| {
+            LinkingError {
+                message: format!("chmod: {}", e),
+            }
+        });
+    }
+
+    // Pipe the contents to sudo rather than staging them in a shared temp dir,
+    // where another user could swap the file before it is installed
+    let child = Command::new("sudo")
+        .arg("install")
+        .arg("-m")
+        .arg(format!("{:o}", mode))
+        .arg("/dev/stdin")
+        .arg(target_path)
+        .stdin(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn();
+    let output = child.and_then(|mut child| {
+        use std::io::Write;
+        // Dropping stdin closes the pipe so install sees the end of the file. A
+        // failed write is reported after the exit status, which explains it better
+        let written = match child.stdin.take() {
+            Some(mut stdin) => stdin.write_all(contents),
+            None => Ok(()),
+        };
+        let output = child.wait_with_output()?;
+        if output.status.success() {
+            written?;
+        }
+        Ok(output)
+    });
+    match output {
+        Ok(o) if o.status.success() => Ok(()),
+        Ok(o) => Err(LinkingError {
+            message: format!(
+                "sudo install failed: {}",
+                String::from_utf8_lossy(&o.stderr)
+            ),
+        }),
+        Err(e) => Err(LinkingError {
+            message: format!("exec sudo install: {}", e),
+        }),
+    }
+}
+
+fn hard_link(source_path: &Path, target_path: &Path, root: bool) -> Result<(), LinkingError> {
+    if !root {
+        return fs::hard_link(source_path, target_path).map_err(|e| LinkingError {
+            message: format!("hardlink: {}", e),
+        });
+    }
+    let output = Command::new("sudo")
+        .arg("ln")
+        .arg(source_path)
+        .arg(target_path)
+        .output();
+    match output {
+        Ok(o) if o.status.success() => Ok(()),
+        Ok(o) => Err(LinkingError {
+            message: format!("sudo ln failed: {}", String::from_utf8_lossy(&o.stderr)),
+        }),
+        Err(e) => Err(LinkingError {
+            message: format!("exec sudo ln: {}", e),
+        }),
+    }
+}
+
+fn print_unlink_result(record: &LinkRecord, result: Result<(), UnlinkError>) {
+    let dst_display = record.target.display().to_string().green();
+    let setup_display = format!("({})", record.setup).cyan();
+    match result {
+        Ok(()) => println!("    {} {} 🗑️", dst_display, setup_display),
+        Err(e) if e.keeps_record() => {
+            println!("    {} {} ❌ {}", dst_display, setup_display, e.message())
+        }
+        Err(e) => println!("    {} {} ⚠️  {}", dst_display, setup_display, e.message()),
+    }
+}
+
 trait Linkable {
     fn source_path(&self) -> PathBuf;
     fn target_path(&self) -> PathBuf;
     fn requires_root(&self) -> bool {
         false
     }
+    fn link_mode(&self) -> LinkMode {
+        LinkMode::Symlink
+    }
+    /// Variables to render the source with; `Some` means the target is a rendered file, not a symlink
+    fn template_vars(&self) -> Option<&TemplateVars> {
+        None
+    }
     fn display_info() -> &'static str;
 
-    fn link(&self) -> Result<(), LinkingError> {
+    /// Contents owl writes to the target, for rendered templates and copies.
+    fn expected_contents(&self) -> Option<Result<Vec<u8>, String>> {
+        let source_path = self.source_path();
+        if let Some(vars) = self.template_vars() {
+            return Some(template::render_file(&source_path, vars).map(String::into_bytes));
+        }
+        match self.link_mode() {
+            LinkMode::Copy => Some(
+                fs::read(&source_path)
+                    .map_err(|e| format!("read {}: {}", source_path.display(), e)),
+            ),
+            LinkMode::Symlink | LinkMode::Hardlink => None,
+        }
+    }
+
+    /// Short description for anything that is not a plain symlink.
+    fn mode_label(&self) -> Option<&'static str> {
+        if self.template_vars().is_some() {
+            return Some("template");
+        }
+        match self.link_mode() {
+            LinkMode::Symlink => None,
+            LinkMode::Copy => Some("copy"),
+            LinkMode::Hardlink => Some("hardlink"),
+        }
+    }
+
+    fn arrow(&self) -> &'static str {
+        if self.mode_label().is_some() {
+            "⇒"
+        } else {
+            "→"
+        }
+    }
+
+    /// Create the target. Returns the hash of the written contents when the
+    /// target is a file owl wrote rather than a symlink.
+    fn link(&self, state: &mut OwlState) -> Result<Option<String>, LinkingError> {
         let target_path = self.target_path();
         let root = self.requires_root();
         let source_path = self.source_path();
@@ -1333,24 +3435,42 @@ trait Linkable {
             });
         }
 
-        // Carefully remove existing targets:
-        // - If symlink: remove the symlink only
-        // - If file: remove the file
+        // Render/read before touching the target so an error leaves it intact
+        let contents = self
+            .expected_contents()
+            .transpose()
+            .map_err(|message| LinkingError { message })?;
+
+        // Carefully clear existing targets:
+        // - If symlink owned by owl (or pointing into the owl repo): remove the symlink only
+        // - If file owl wrote and nobody edited since, or a hardlink of the source: remove it
+        // - If any other symlink or file: move it into the backup store
         // - If directory: remove ONLY if empty; otherwise fail with a clear message
         if target_path.is_symlink() {
-            if let Err(e) = std::fs::remove_file(&target_path) {
-                return Err(LinkingError {
-                    message: format!("remove old symlink: {}", e),
-                });
+            // Links made before the state manifest existed still point into the repo
+            let points_into_repo = std::fs::read_link(&target_path)
+                .map(|dest| dest == source_path || dest.starts_with(get_config().owl_path))
+                .unwrap_or(false);
+            let owned = state.find_link(&target_path).is_some();
+            if points_into_repo || owned {
+                if let Err(e) = std::fs::remove_file(&target_path) {
+                    return Err(LinkingError {
+                        message: format!("remove old symlink: {}", e),
+                    });
+                }
+            } else {
+                backup::backup_target(&target_path, root, state)?;
             }
         } else if target_path.exists() {
             match std::fs::symlink_metadata(&target_path) {
                 Ok(meta) => {
                     if meta.is_file() {
-                        if let Err(e) = std::fs::remove_file(&target_path) {
-                            return Err(LinkingError {
-                                message: format!("remove old file: {}", e),
-                            });
+                        if state.owns_unmodified_file(&target_path)
+                            || is_same_file(&target_path, &source_path)
+                        {
+                            remove_target_file(&target_path, root)?;
+                        } else {
+                            backup::backup_target(&target_path, root, state)?;
                         }
                     } else if meta.is_dir() {
                         match std::fs::read_dir(&target_path) {
@@ -1416,6 +3536,15 @@ trait Linkable {
             }
         }
 
+        if let Some(contents) = contents {
+            write_target_file(&source_path, &target_path, &contents, root)?;
+            return Ok(Some(state::content_hash(&contents)));
+        }
+
+        if self.link_mode() == LinkMode::Hardlink {
+            return hard_link(&source_path, &target_path, root).map(|_| None);
+        }
+
         if root {
             let output = Command::new("sudo")
                 .arg("ln")
@@ -1424,7 +3553,7 @@ trait Linkable {
                 .arg(&target_path)
                 .output();
             match output {
-                Ok(o) if o.status.success() => Ok(()),
+                Ok(o) if o.status.success() => Ok(None),
                 Ok(o) => {
                     let msg = format!("sudo ln failed: {}", String::from_utf8_lossy(&o.stderr));
                     Err(LinkingError { message: msg })
@@ -1434,21 +3563,23 @@ trait Linkable {
                 }),
             }
         } else {
-            std::os::unix::fs::symlink(&source_path, &target_path).map_err(|e| LinkingError {
-                message: format!("symlink: {}", e),
-            })
+            std::os::unix::fs::symlink(&source_path, &target_path)
+                .map(|_| None)
+                .map_err(|e| LinkingError {
+                    message: format!("symlink: {}", e),
+                })
         }
     }
 }
 
 fn for_each_dep_depth_first<F>(start_name: &str, mut f: F)
 where
-    F: FnMut(&Setup),
+    F: FnMut(Setup),
 {
     let mut visited = std::collections::HashSet::new();
     fn walk<F>(name: &str, visited: &mut std::collections::HashSet<String>, f: &mut F)
     where
-        F: FnMut(&Setup),
+        F: FnMut(Setup),
     {
         if visited.contains(name) {
             return;
@@ -1458,7 +3589,7 @@ where
         for dep in &setup.dependencies {
             walk(dep.name.as_str(), visited, f);
         }
-        f(&setup);
+        f(setup);
     }
     walk(start_name, &mut visited, &mut f);
 }
@@ -0,0 +1,436 @@
+use colored::Colorize;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::when;
+use crate::{output_tag, print_subsection, run_tagged};
+
+// =======================================
+//            Packages
+// =======================================
+//
+// Setups declare packages per package manager. System managers (pacman, apt,
+// dnf, brew) are resolved against the detected OS and installed in one batch
+// for the whole graph before any install script runs. Language managers
+// (cargo, npm) usually depend on a toolchain another setup installs, so their
+// packages are installed right before the declaring setup's install script.
+//
+// Setting `OWL_FAKE_PACKAGES=<file>` swaps every backend for a fake one that
+// records installed packages in that file, so all of this can be exercised
+// without root.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Manager {
+    Pacman,
+    Apt,
+    Dnf,
+    Brew,
+    Cargo,
+    Npm,
+}
+
+pub type PackageSet = BTreeMap<Manager, Vec<String>>;
+
+impl Manager {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Manager::Pacman => "pacman",
+            Manager::Apt => "apt",
+            Manager::Dnf => "dnf",
+            Manager::Brew => "brew",
+            Manager::Cargo => "cargo",
+            Manager::Npm => "npm",
+        }
+    }
+
+    pub fn is_system(&self) -> bool {
+        !matches!(self, Manager::Cargo | Manager::Npm)
+    }
+
+    /// Whether packages for this manager apply on this machine.
+    pub fn applies(&self) -> bool {
+        let os_ids = when::os_ids();
+        let os_is = |ids: &[&str]| os_ids.iter().any(|id| ids.contains(&id.as_str()));
+        match self {
+            Manager::Pacman => os_is(&["arch"]),
+            Manager::Apt => os_is(&["debian", "ubuntu"]),
+            Manager::Dnf => os_is(&["fedora", "rhel", "centos"]),
+            Manager::Brew => os_is(&["macos"]),
+            Manager::Cargo | Manager::Npm => true,
+        }
+    }
+}
+
+pub trait PackageBackend {
+    /// The subset of `packages` that is not installed yet.
+    fn missing(&self, packages: &[String]) -> Vec<String>;
+    /// Install `packages`, putting `tag` in front of every line of output.
+    fn install(&self, packages: &[String], tag: &str) -> Result<(), String>;
+}
+
+/// Talks to the real package manager.
+struct SystemBackend {
+    manager: Manager,
+}
+
+fn succeeds(cmd: &mut Command) -> bool {
+    cmd.stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+impl SystemBackend {
+    fn is_installed(&self, package: &str) -> bool {
+        match self.manager {
+            Manager::Pacman => succeeds(Command::new("pacman").arg("-Q").arg(package)),
+            Manager::Apt => Command::new("dpkg-query")
+                .args(["-W", "-f=${Status}"])
+                .arg(package)
+                .output()
+                .map(|o| String::from_utf8_lossy(&o.stdout).trim() == "install ok installed")
+                .unwrap_or(false),
+            Manager::Dnf => succeeds(Command::new("rpm").arg("-q").arg(package)),
+            Manager::Brew => succeeds(
+                Command::new("brew")
+                    .args(["list", "--versions"])
+                    .arg(package),
+            ),
+            Manager::Cargo => Command::new("cargo")
+                .args(["install", "--list"])
+                .output()
+                .map(|o| {
+                    // Crate lines look like "ripgrep v14.1.0:"; binaries are indented below
+                    String::from_utf8_lossy(&o.stdout)
+                        .lines()
+                        .any(|l| l.split_whitespace().next() == Some(package))
+                })
+                .unwrap_or(false),
+            Manager::Npm => succeeds(
+                Command::new("npm")
+                    .args(["ls", "-g", "--depth=0"])
+                    .arg(package),
+            ),
+        }
+    }
+
+    fn install_command(&self) -> Command {
+        let (program, args): (&str, &[&str]) = match self.manager {
+            Manager::Pacman => ("sudo", &["pacman", "-S", "--needed", "--noconfirm"]),
+            Manager::Apt => ("sudo", &["apt-get", "install", "-y"]),
+            Manager::Dnf => ("sudo", &["dnf", "install", "-y"]),
+            Manager::Brew => ("brew", &["install"]),
+            Manager::Cargo => ("cargo", &["install"]),
+            Manager::Npm => ("npm", &["install", "-g"]),
+        };
+        let mut cmd = Command::new(program);
+        cmd.args(args);
+        cmd
+    }
+}
+
+impl PackageBackend for SystemBackend {
+    fn missing(&self, packages: &[String]) -> Vec<String> {
+        packages
+            .iter()
+            .filter(|p| !self.is_installed(p))
+            .cloned()
+            .collect()
+    }
+
+    fn install(&self, packages: &[String], tag: &str) -> Result<(), String> {
+        let status = run_tagged(self.install_command().args(packages), tag);
+        match status {
+            Ok(s) if s.success() => Ok(()),
+            Ok(s) => Err(format!(
+                "{} exited with code {:?}",
+                self.manager.name(),
+                s.code()
+            )),
+            Err(e) => Err(format!("exec {}: {}", self.manager.name(), e)),
+        }
+    }
+}
+
+/// Records "<manager> <package>" lines in a plain file instead of installing anything.
+struct FakeBackend {
+    manager: Manager,
+    db: PathBuf,
+}
+
+impl FakeBackend {
+    fn new(manager: Manager, db: impl Into<PathBuf>) -> Self {
+        FakeBackend {
+            manager,
+            db: db.into(),
+        }
+    }
+
+    fn installed(&self) -> Vec<String> {
+        let prefix = format!("{} ", self.manager.name());
+        std::fs::read_to_string(&self.db)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|l| l.strip_prefix(&prefix).map(|p| p.trim().to_string()))
+            .collect()
+    }
+}
+
+impl PackageBackend for FakeBackend {
+    fn missing(&self, packages: &[String]) -> Vec<String> {
+        let installed = self.installed();
+        packages
+            .iter()
+            .filter(|p| !installed.contains(p))
+            .cloned()
+            .collect()
+    }
+
+    fn install(&self, packages: &[String], _tag: &str) -> Result<(), String> {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.db)
+            .map_err(|e| format!("open {}: {}", self.db.display(), e))?;
+        for p in packages {
+            writeln!(file, "{} {}", self.manager.name(), p)
+                .map_err(|e| format!("write {}: {}", self.db.display(), e))?;
+        }
+        Ok(())
+    }
+}
+
+pub fn backend(manager: Manager) -> Box<dyn PackageBackend> {
+    match std::env::var_os("OWL_FAKE_PACKAGES") {
+        Some(db) => Box::new(FakeBackend::new(manager, db)),
+        None => Box::new(SystemBackend { manager }),
+    }
+}
+
+fn dedup(packages: &mut Vec<String>) {
+    let mut seen = std::collections::HashSet::new();
+    packages.retain(|p| seen.insert(p.clone()));
+}
+
+/// Install `packages` with one transaction, skipping those already present.
+fn install_batch(
+    manager: Manager,
+    backend: &dyn PackageBackend,
+    mut packages: Vec<String>,
+    tag: &str,
+) -> Result<(), String> {
+    dedup(&mut packages);
+    let missing = backend.missing(&packages);
+    let present = packages.len() - missing.len();
+    if missing.is_empty() {
+        println!(
+            "{}    {} {}",
+            tag,
+            format!("{}:", manager.name()).yellow(),
+            format!("all {} installed", packages.len()).green()
+        );
+        return Ok(());
+    }
+    println!(
+        "{}    {} {} {}",
+        tag,
+        format!("{}:", manager.name()).yellow(),
+        missing.join(" ").green(),
+        format!("({} already installed)", present).dimmed()
+    );
+    backend.install(&missing, tag)
+}
+
+/// Install the system packages declared by every setup in one batch per manager.
+/// Returns each setup whose packages could not be installed, with the error.
+pub fn install_system_packages(setups: &[(String, PackageSet)]) -> Vec<(String, String)> {
+    install_system_packages_with(setups, |m| m.is_system() && m.applies(), backend)
+}
+
+/// Batch the packages of every manager `selected` accepts, installing them through `backend`.
+fn install_system_packages_with(
+    setups: &[(String, PackageSet)],
+    selected: impl Fn(Manager) -> bool,
+    backend: impl Fn(Manager) -> Box<dyn PackageBackend>,
+) -> Vec<(String, String)> {
+    let mut batches: BTreeMap<Manager, (Vec<String>, Vec<String>)> = BTreeMap::new();
+    for (setup, packages) in setups {
+        for (manager, names) in packages {
+            if !selected(*manager) || names.is_empty() {
+                continue;
+            }
+            let (batch, owners) = batches.entry(*manager).or_default();
+            batch.extend(names.iter().cloned());
+            owners.push(setup.clone());
+        }
+    }
+    if batches.is_empty() {
+        return Vec::new();
+    }
+
+    println!("{}", "📦 Packages".magenta().bold());
+    let mut failures = Vec::new();
+    for (manager, (batch, owners)) in batches {
+        if let Err(e) = install_batch(manager, backend(manager).as_ref(), batch, "") {
+            println!("    ❌ {}", e);
+            failures.extend(owners.into_iter().map(|o| (o, format!("packages: {}", e))));
+        }
+    }
+    failures
+}
+
+/// Install the language-manager (cargo, npm) packages of a single setup,
+/// tagging output with `prefix` like its install script.
+pub fn install_language_packages(
+    packages: &PackageSet,
+    prefix: Option<&str>,
+) -> Result<(), String> {
+    let wanted: Vec<_> = packages
+        .iter()
+        .filter(|(m, names)| !m.is_system() && m.applies() && !names.is_empty())
+        .collect();
+    if wanted.is_empty() {
+        return Ok(());
+    }
+    let tag = output_tag(prefix);
+    println!("{}  {}", tag, "Packages".green().bold());
+    for (manager, names) in wanted {
+        install_batch(*manager, backend(*manager).as_ref(), names.clone(), &tag)
+            .map_err(|e| format!("packages: {}", e))?;
+    }
+    Ok(())
+}
+
+pub fn print_packages_plan(packages: &PackageSet) {
+    if packages.is_empty() {
+        return;
+    }
+    print_subsection("Packages");
+    for (manager, names) in packages {
+        let label = format!("{}:", manager.name()).yellow();
+        if manager.applies() {
+            println!("    {} {}", label, names.join(" ").green());
+        } else {
+            println!(
+                "    {} {} {}",
+                label,
+                names.join(" ").dimmed(),
+                "(not used on this machine)".dimmed()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// Remembers what it installed, failing every install when `fail` is set.
+    #[derive(Default)]
+    struct TestBackend {
+        installed: RefCell<Vec<String>>,
+        fail: bool,
+    }
+
+    impl PackageBackend for TestBackend {
+        fn missing(&self, packages: &[String]) -> Vec<String> {
+            let installed = self.installed.borrow();
+            packages
+                .iter()
+                .filter(|p| !installed.contains(p))
+                .cloned()
+                .collect()
+        }
+
+        fn install(&self, packages: &[String], _tag: &str) -> Result<(), String> {
+            if self.fail {
+                return Err("test backend refuses to install".to_string());
+            }
+            self.installed.borrow_mut().extend(packages.iter().cloned());
+            Ok(())
+        }
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn fake_backend_records_packages_per_manager() {
+        let db = std::env::temp_dir().join(format!("owl-fake-packages-{}", std::process::id()));
+        let _ = std::fs::remove_file(&db);
+
+        let cargo = FakeBackend::new(Manager::Cargo, &db);
+        assert_eq!(cargo.missing(&names(&["ripgrep"])), names(&["ripgrep"]));
+        cargo.install(&names(&["ripgrep"]), "").unwrap();
+        assert!(cargo.missing(&names(&["ripgrep"])).is_empty());
+        // Another manager's packages are tracked separately
+        let npm = FakeBackend::new(Manager::Npm, &db);
+        assert_eq!(npm.missing(&names(&["ripgrep"])), names(&["ripgrep"]));
+        assert_eq!(std::fs::read_to_string(&db).unwrap(), "cargo ripgrep\n");
+
+        let _ = std::fs::remove_file(&db);
+    }
+
+    #[test]
+    fn batch_installs_only_missing_packages_once() {
+        let backend = TestBackend::default();
+        backend.installed.borrow_mut().push("git".to_string());
+        install_batch(Manager::Cargo, &backend, names(&["git", "fd", "fd"]), "").unwrap();
+        assert_eq!(*backend.installed.borrow(), names(&["git", "fd"]));
+    }
+
+    #[test]
+    fn failed_batch_fails_every_setup_that_asked_for_it() {
+        let manager = Manager::Apt;
+        let setups = vec![
+            (
+                "a".to_string(),
+                PackageSet::from([(manager, names(&["git"]))]),
+            ),
+            (
+                "b".to_string(),
+                PackageSet::from([(manager, names(&["fd"]))]),
+            ),
+            (
+                "c".to_string(),
+                PackageSet::from([(Manager::Cargo, names(&["x"]))]),
+            ),
+        ];
+        let failures = install_system_packages_with(
+            &setups,
+            |m| m.is_system(),
+            |_| {
+                Box::new(TestBackend {
+                    fail: true,
+                    ..Default::default()
+                })
+            },
+        );
+        let failed: Vec<&str> = failures.iter().map(|(s, _)| s.as_str()).collect();
+        // Language packages are not part of the system batch
+        assert_eq!(failed, ["a", "b"]);
+        assert!(failures[0].1.starts_with("packages: "));
+    }
+
+    #[test]
+    fn successful_batch_reports_no_failures() {
+        let setups = vec![(
+            "a".to_string(),
+            PackageSet::from([(Manager::Apt, names(&["git"]))]),
+        )];
+        let failures = install_system_packages_with(
+            &setups,
+            |m| m.is_system(),
+            |_| Box::new(TestBackend::default()),
+        );
+        assert!(failures.is_empty());
+    }
+}
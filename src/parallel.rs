@@ -0,0 +1,86 @@
+use std::collections::HashSet;
+use std::sync::mpsc;
+
+use crate::report::{RunReport, SetupOutcome};
+use crate::{Operation, RunOptions, Setup};
+
+// =======================================
+//            Parallel Installs
+// =======================================
+//
+// Install scripts form a DAG: a setup may start as soon as all of its
+// dependencies have finished. Up to `jobs` scripts run at once, and a
+// `serial` setup only ever runs on its own.
+
+/// Run the install scripts of `setups` (in dependency order, as produced by
+/// the depth-first walk) concurrently, recording each outcome in `report`.
+pub fn run_installs(setups: &[Setup], opts: &RunOptions, report: &mut RunReport) {
+    let jobs = opts.jobs.max(1);
+    let mut pending: Vec<&Setup> = setups.iter().collect();
+    let mut finished: HashSet<&str> = HashSet::new();
+    let mut running = 0;
+    let mut serial_running = false;
+
+    std::thread::scope(|scope| {
+        let (tx, rx) = mpsc::channel::<(&Setup, Result<(), String>)>();
+        loop {
+            // Start everything whose dependencies are done, in walk order
+            let mut i = 0;
+            while i < pending.len() {
+                let s = pending[i];
+                if report.contains(&s.name) {
+                    // Already failed before any script ran (e.g. its packages)
+                    finished.insert(&s.name);
+                    pending.remove(i);
+                    i = 0;
+                    continue;
+                }
+                let deps_done = s
+                    .dependencies
+                    .iter()
+                    .all(|d| finished.contains(d.name.as_str()));
+                if !deps_done {
+                    i += 1;
+                    continue;
+                }
+                if let Some(dep) = s.failed_dependency(report, opts.keep_going) {
+                    report.skip(&s.name, dep);
+                    finished.insert(&s.name);
+                    pending.remove(i);
+                    // Something earlier in the list may have been waiting on it
+                    i = 0;
+                    continue;
+                }
+                let has_room = running < jobs && !serial_running;
+                if !has_room || (s.serial && running > 0) {
+                    // Hold later setups back so a waiting serial setup gets the next slot
+                    break;
+                }
+
+                pending.remove(i);
+                running += 1;
+                serial_running = s.serial;
+                let tx = tx.clone();
+                scope.spawn(move || {
+                    s.print_op_header(Operation::Install, Some(&s.name));
+                    let result = s.install_once(&opts.install, Some(&s.name));
+                    let _ = tx.send((s, result));
+                });
+            }
+
+            if running == 0 {
+                break;
+            }
+            let Ok((s, result)) = rx.recv() else {
+                break;
+            };
+            running -= 1;
+            if s.serial {
+                serial_running = false;
+            }
+            finished.insert(&s.name);
+            let errors = result.err().into_iter().collect();
+            report.record(&s.name, SetupOutcome::from_errors(errors));
+        }
+    });
+}
@@ -0,0 +1,391 @@
+use colored::Colorize;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::shell::{fish_value, sh_value, write_file, write_generated, Shell, GENERATED_HEADER};
+use crate::{
+    print_section, print_subsection, tilde_expand_path, Linkable, Setup, ValidatedRunScript,
+};
+
+// =======================================
+//            RC Loaders
+// =======================================
+//
+// rc scripts are linked into one directory per shell under ~/.config/owl/rc/.
+// For every shell the nest uses, owl writes a loader, ~/.config/owl/owl-start.<shell>,
+// that sources the generated environment, the rc scripts that shell can run
+// and the generated aliases, in that order.
+//
+// rc scripts run by their `order` (lower first, 0 by default), then dependencies
+// before dependents, then in the order a setup lists them. The loaders list
+// every script explicitly so that order never depends on file names.
+//
+// With `rc_bundle` set on the nest, the rc scripts are instead concatenated in
+// dependency order into one bundle per shell. The bash, zsh and fish loaders
+// check whether any script changed since the bundle was written and run
+// `owl rc generate` to rebuild it before sourcing.
+
+fn get_loader_path(shell: Shell) -> PathBuf {
+    tilde_expand_path(&format!("~/.config/owl/owl-start.{}", shell.name()))
+}
+
+fn get_bundle_path(shell: Shell) -> PathBuf {
+    tilde_expand_path(&format!("~/.config/owl/bundle/rc.{}", shell.name()))
+}
+
+fn generated_file(dir: &str, file: &str) -> PathBuf {
+    tilde_expand_path(&format!("~/.config/owl/{}/{}", dir, file))
+}
+
+/// Shells the graph uses: bash and zsh always, since `owl-start.sh` sources their
+/// loaders for the generated environment and aliases even without rc scripts,
+/// plus any other shell with a setup of the same name or rc scripts to run.
+fn shells_in_graph(setups: &[Setup]) -> Vec<Shell> {
+    Shell::ALL
+        .into_iter()
+        .filter(|shell| {
+            matches!(shell, Shell::Bash | Shell::Zsh)
+                || setups.iter().any(|s| {
+                    s.name == shell.name() || s.rc_scripts.iter().any(|r| r.shell.runs_in(*shell))
+                })
+        })
+        .collect()
+}
+
+pub struct RcEntry<'a> {
+    pub setup: &'a str,
+    pub script: &'a ValidatedRunScript,
+}
+
+/// The rc scripts `shell` runs, by `order`, then in dependency order (`setups`
+/// comes from the depth-first walk) and then in the order each setup lists them.
+pub fn scripts_for(setups: &[Setup], shell: Shell) -> Vec<RcEntry<'_>> {
+    let mut entries: Vec<RcEntry> = setups
+        .iter()
+        .flat_map(|s| {
+            s.rc_scripts
+                .iter()
+                .filter(move |r| r.shell.runs_in(shell))
+                .map(move |script| RcEntry {
+                    setup: &s.name,
+                    script,
+                })
+        })
+        .collect();
+    // Stable, so ties keep the walk order
+    entries.sort_by_key(|e| e.script.order);
+    entries
+}
+
+fn render_bundle(entries: &[RcEntry]) -> String {
+    let mut out = format!("# {}\n", GENERATED_HEADER);
+    for entry in entries {
+        let source = entry.script.source_path();
+        out.push_str(&format!(
+            "\n# ---- {} ({}) ----\n",
+            source.display(),
+            entry.setup
+        ));
+        match std::fs::read_to_string(&source) {
+            Ok(contents) => {
+                out.push_str(&contents);
+                if !contents.ends_with('\n') {
+                    out.push('\n');
+                }
+            }
+            // e.g. a secret that has not been rendered yet
+            Err(e) => out.push_str(&format!("# skipped: {}\n", e)),
+        }
+    }
+    out
+}
+
+fn render_posix(shell: Shell, entries: &[RcEntry], bundle: bool) -> String {
+    let mut out = format!("# {}\n", GENERATED_HEADER);
+    let source_if_present = |path: &Path| {
+        let path = sh_value(&path.display().to_string());
+        format!("[ -f {} ] && . {}\n", path, path)
+    };
+    out.push_str(&source_if_present(&generated_file("env", "env.sh")));
+    if bundle {
+        let bundle = sh_value(&get_bundle_path(shell).display().to_string());
+        let sources: Vec<String> = entries
+            .iter()
+            .map(|e| sh_value(&e.script.source_path().display().to_string()))
+            .collect();
+        out.push_str(&format!(
+            "for owl_src in {}; do\n    if [ \"$owl_src\" -nt {} ]; then\n        owl rc generate >/dev/null 2>&1\n        break\n    fi\ndone\nunset owl_src\n",
+            sources.join(" "),
+            bundle
+        ));
+        out.push_str(&format!("[ -f {} ] && . {}\n", bundle, bundle));
+    } else {
+        for entry in entries {
+            out.push_str(&source_if_present(&entry.script.target_path()));
+        }
+    }
+    out.push_str(&source_if_present(&generated_file("aliases", "aliases.sh")));
+    out
+}
+
+fn render_fish(entries: &[RcEntry], bundle: bool) -> String {
+    let mut out = format!("# {}\n", GENERATED_HEADER);
+    let source_if_present = |path: &Path| {
+        let path = fish_value(&path.display().to_string());
+        format!("test -f {}; and source {}\n", path, path)
+    };
+    out.push_str(&source_if_present(&generated_file("env", "env.fish")));
+    if bundle {
+        let bundle = fish_value(&get_bundle_path(Shell::Fish).display().to_string());
+        let sources: Vec<String> = entries
+            .iter()
+            .map(|e| fish_value(&e.script.source_path().display().to_string()))
+            .collect();
+        out.push_str(&format!(
+            "for owl_src in {}\n    if test $owl_src -nt {}\n        owl rc generate >/dev/null 2>&1\n        break\n    end\nend\n",
+            sources.join(" "),
+            bundle
+        ));
+        out.push_str(&format!("test -f {}; and source {}\n", bundle, bundle));
+    } else {
+        for entry in entries {
+            out.push_str(&source_if_present(&entry.script.target_path()));
+        }
+    }
+    out.push_str(&source_if_present(&generated_file(
+        "aliases",
+        "aliases.fish",
+    )));
+    out
+}
+
+/// nushell resolves `source` while parsing, so every file is listed explicitly
+/// and only when it exists.
+fn render_nu(entries: &[RcEntry], bundle: bool) -> String {
+    let mut files = vec![generated_file("env", "env.nu")];
+    if bundle {
+        files.push(get_bundle_path(Shell::Nu));
+    } else {
+        files.extend(entries.iter().map(|e| e.script.target_path()));
+    }
+    files.push(generated_file("aliases", "aliases.nu"));
+
+    let mut out = format!("# {}\n", GENERATED_HEADER);
+    for file in files.iter().filter(|f| f.exists()) {
+        out.push_str(&format!("source '{}'\n", file.display()));
+    }
+    out
+}
+
+fn write_and_report(
+    path: &Path,
+    write: fn(&Path, &str) -> Result<(), String>,
+    contents: &str,
+    errors: &mut Vec<String>,
+) {
+    match write(path, contents) {
+        Ok(()) => println!("    {} ✅", path.display().to_string().green()),
+        Err(e) => {
+            println!("    {} ❌ {}", path.display().to_string().green(), e);
+            errors.push(e);
+        }
+    }
+}
+
+fn remove_if_present(path: &Path, errors: &mut Vec<String>) {
+    if !path.exists() {
+        return;
+    }
+    match std::fs::remove_file(path) {
+        Ok(()) => println!("    {} 🗑️", path.display().to_string().blue()),
+        Err(e) => errors.push(format!("remove {}: {}", path.display(), e)),
+    }
+}
+
+/// Write the loader (and, with `bundle`, the rc bundle) of every shell the graph
+/// uses, and remove those of shells it no longer does.
+pub fn generate_loaders(setups: &[Setup], bundle: bool) -> Result<(), Vec<String>> {
+    println!("{}", "🐚 Shell Loaders".magenta().bold());
+    let shells = shells_in_graph(setups);
+    let mut errors = Vec::new();
+    for shell in Shell::ALL {
+        let loader_path = get_loader_path(shell);
+        let bundle_path = get_bundle_path(shell);
+        if !shells.contains(&shell) {
+            remove_if_present(&loader_path, &mut errors);
+            remove_if_present(&bundle_path, &mut errors);
+            continue;
+        }
+        let entries = scripts_for(setups, shell);
+        if bundle {
+            // Written first, since the nu loader only lists files that exist. Always
+            // rewritten so its mtime is newer than every script the loaders compare it to
+            let contents = render_bundle(&entries);
+            write_and_report(&bundle_path, write_file, &contents, &mut errors);
+        } else {
+            remove_if_present(&bundle_path, &mut errors);
+        }
+        let contents = match shell {
+            Shell::Bash | Shell::Zsh => render_posix(shell, &entries, bundle),
+            Shell::Fish => render_fish(&entries, bundle),
+            Shell::Nu => render_nu(&entries, bundle),
+        };
+        write_and_report(&loader_path, write_generated, &contents, &mut errors);
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Print the order each shell sources the rc scripts of the graph in.
+pub fn print_order(setups: &[Setup]) {
+    let shells = shells_in_graph(setups);
+    if shells.iter().all(|s| scripts_for(setups, *s).is_empty()) {
+        return;
+    }
+    println!("{}", "📜 RC Order".magenta().bold());
+    for shell in shells {
+        let entries = scripts_for(setups, shell);
+        if entries.is_empty() {
+            continue;
+        }
+        print_subsection(shell.name());
+        let width = entries.len().to_string().len();
+        for (i, entry) in entries.iter().enumerate() {
+            let order = match entry.script.order {
+                0 => String::new(),
+                n => format!(" [order {}]", n),
+            };
+            println!(
+                "    {:>width$}. {} {}{}",
+                i + 1,
+                entry.script.source_path().display().to_string().green(),
+                format!("({})", entry.setup).cyan(),
+                order.yellow(),
+                width = width
+            );
+        }
+    }
+}
+
+// ---------- Profiling ----------
+
+/// The command that sources `files` in a fresh, non-interactive `shell`
+/// without reading its own startup files.
+fn source_command(shell: Shell, files: &[PathBuf]) -> Command {
+    let (program, args, script): (&str, &[&str], String) = match shell {
+        Shell::Bash => (
+            "bash",
+            &["--noprofile", "--norc", "-c"],
+            posix_sources(files),
+        ),
+        Shell::Zsh => ("zsh", &["-f", "-c"], posix_sources(files)),
+        Shell::Fish => (
+            "fish",
+            &["--no-config", "-c"],
+            files
+                .iter()
+                .map(|f| format!("source {}; ", fish_value(&f.display().to_string())))
+                .collect(),
+        ),
+        Shell::Nu => (
+            "nu",
+            &["--no-config-file", "-c"],
+            files
+                .iter()
+                .map(|f| format!("source '{}'; ", f.display()))
+                .collect(),
+        ),
+    };
+    let mut cmd = Command::new(program);
+    cmd.args(args).arg(script);
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    cmd
+}
+
+fn posix_sources(files: &[PathBuf]) -> String {
+    files
+        .iter()
+        .map(|f| format!(". {}; ", sh_value(&f.display().to_string())))
+        .collect()
+}
+
+/// Fastest of `runs` runs, or an error if the shell could not be started.
+fn time_sourcing(shell: Shell, files: &[PathBuf], runs: usize) -> Result<Duration, String> {
+    let mut fastest: Option<Duration> = None;
+    for _ in 0..runs.max(1) {
+        let start = Instant::now();
+        source_command(shell, files)
+            .status()
+            .map_err(|e| format!("exec {}: {}", shell.name(), e))?;
+        let elapsed = start.elapsed();
+        fastest = Some(fastest.map_or(elapsed, |f| f.min(elapsed)));
+    }
+    Ok(fastest.unwrap_or_default())
+}
+
+fn format_ms(d: Duration) -> String {
+    format!("{:.1}ms", d.as_secs_f64() * 1000.0)
+}
+
+/// Time every rc script `shell` runs, each in its own subshell after the generated
+/// environment, and print the `top` slowest.
+pub fn profile(setups: &[Setup], shell: Shell, runs: usize, top: usize) -> Result<(), String> {
+    let env_file = match shell {
+        Shell::Bash | Shell::Zsh => generated_file("env", "env.sh"),
+        Shell::Fish => generated_file("env", "env.fish"),
+        Shell::Nu => generated_file("env", "env.nu"),
+    };
+    let base: Vec<PathBuf> = [env_file].into_iter().filter(|f| f.exists()).collect();
+    // Starting the shell and loading the environment is paid once, not per script
+    let baseline = time_sourcing(shell, &base, runs)?;
+
+    let mut timings = Vec::new();
+    for entry in scripts_for(setups, shell) {
+        let source = entry.script.source_path();
+        if !source.exists() {
+            continue;
+        }
+        let mut files = base.clone();
+        files.push(source.clone());
+        let elapsed = time_sourcing(shell, &files, runs)?;
+        timings.push((elapsed.saturating_sub(baseline), entry.setup, source));
+    }
+    timings.sort_by_key(|t| std::cmp::Reverse(t.0));
+
+    print_section(&format!("RC Profile ({})", shell.name()));
+    println!(
+        "  {} {}",
+        "Shell startup:".white(),
+        format_ms(baseline).cyan()
+    );
+    let total: Duration = timings.iter().map(|t| t.0).sum();
+    let width = timings.iter().map(|t| t.1.len()).max().unwrap_or(0);
+    for (elapsed, setup, source) in timings.iter().take(top) {
+        let ms = format!("{:>9}", format_ms(*elapsed));
+        let ms = if elapsed.as_millis() >= 50 {
+            ms.red()
+        } else if elapsed.as_millis() >= 10 {
+            ms.yellow()
+        } else {
+            ms.green()
+        };
+        println!(
+            "  {} {} {}",
+            ms,
+            format!("{:width$}", setup, width = width).cyan(),
+            source.display().to_string().dimmed()
+        );
+    }
+    println!(
+        "\n{} rc scripts, {} in total",
+        timings.len().to_string().cyan(),
+        format_ms(total).bold()
+    );
+    Ok(())
+}
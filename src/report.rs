@@ -0,0 +1,115 @@
+use colored::Colorize;
+
+use crate::print_section;
+
+// =======================================
+//            Run Report
+// =======================================
+//
+// Collects how each setup fared while an operation walks the dependency
+// graph, so failures can skip dependents and decide the exit code.
+
+pub enum SetupOutcome {
+    Ok,
+    Failed(Vec<String>),
+    /// Not attempted because a dependency failed
+    Skipped(String),
+}
+
+impl SetupOutcome {
+    pub fn from_errors(errors: Vec<String>) -> Self {
+        if errors.is_empty() {
+            SetupOutcome::Ok
+        } else {
+            SetupOutcome::Failed(errors)
+        }
+    }
+}
+
+struct SetupResult {
+    name: String,
+    outcome: SetupOutcome,
+}
+
+#[derive(Default)]
+pub struct RunReport {
+    results: Vec<SetupResult>,
+}
+
+impl RunReport {
+    pub fn record(&mut self, name: &str, outcome: SetupOutcome) {
+        self.results.push(SetupResult {
+            name: name.to_string(),
+            outcome,
+        });
+    }
+
+    /// Record `name` as skipped because `failed_dependency` did not succeed.
+    pub fn skip(&mut self, name: &str, failed_dependency: &str) {
+        let reason = format!("dependency {} failed", failed_dependency);
+        println!(
+            "{} {} ({})",
+            "⏭️  Skipping".yellow().bold(),
+            name.cyan().bold(),
+            reason
+        );
+        self.record(name, SetupOutcome::Skipped(reason));
+    }
+
+    /// True when `name` already has an outcome, e.g. because its packages failed to install.
+    pub fn contains(&self, name: &str) -> bool {
+        self.results.iter().any(|r| r.name == name)
+    }
+
+    /// True when `name` failed or was skipped, so anything built on it should not run.
+    pub fn is_broken(&self, name: &str) -> bool {
+        self.results
+            .iter()
+            .any(|r| r.name == name && !matches!(r.outcome, SetupOutcome::Ok))
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.results
+            .iter()
+            .all(|r| matches!(r.outcome, SetupOutcome::Ok))
+    }
+
+    pub fn print_summary(&self) {
+        let width = self.results.iter().map(|r| r.name.len()).max().unwrap_or(0);
+        println!();
+        print_section("Summary");
+        for result in &self.results {
+            let name = format!("{:width$}", result.name, width = width);
+            match &result.outcome {
+                SetupOutcome::Ok => println!("  ✅ {} {}", name.cyan(), "ok".green()),
+                SetupOutcome::Failed(errors) => {
+                    println!("  ❌ {} {}", name.cyan(), "failed".red());
+                    for error in errors {
+                        println!("     {:width$} {}", "", error.red(), width = width);
+                    }
+                }
+                SetupOutcome::Skipped(reason) => println!(
+                    "  ⏭️  {} {}",
+                    name.cyan(),
+                    format!("skipped ({})", reason).yellow()
+                ),
+            }
+        }
+        let failed = self
+            .results
+            .iter()
+            .filter(|r| matches!(r.outcome, SetupOutcome::Failed(_)))
+            .count();
+        let skipped = self
+            .results
+            .iter()
+            .filter(|r| matches!(r.outcome, SetupOutcome::Skipped(_)))
+            .count();
+        println!(
+            "\n{} ok, {} failed, {} skipped",
+            (self.results.len() - failed - skipped).to_string().green(),
+            failed.to_string().red(),
+            skipped.to_string().yellow()
+        );
+    }
+}
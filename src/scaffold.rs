@@ -0,0 +1,224 @@
+use colored::Colorize;
+use serde_json::{json, Map, Value};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::setup_file::{self, Format};
+use crate::{get_config, get_nest_path, schema, shell};
+
+// =======================================
+//            Scaffolding
+// =======================================
+//
+// `owl setup new` and `owl nest new` create the layout the rest of owl
+// expects, so a new setup validates and links before anything is filled in.
+
+pub struct NewSetupOptions {
+    pub install: bool,
+    pub rc: bool,
+    pub service: bool,
+    pub add_to_nest: bool,
+    pub format: Format,
+}
+
+fn check_name(name: &str, owl_path: &Path) -> Result<(), String> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || name.contains(['/', '\\'])
+        || name.contains(char::is_whitespace)
+    {
+        return Err(format!("invalid name '{}'", name));
+    }
+    // Setups and nests share one namespace; see `load_setup_by_name`
+    for dir in ["setups", "nests"] {
+        let existing = owl_path.join(dir).join(name);
+        if existing.exists() {
+            return Err(format!("{} already exists", existing.display()));
+        }
+    }
+    Ok(())
+}
+
+fn write_file(path: &Path, contents: &str, executable: bool) -> Result<(), String> {
+    shell::write_file(path, contents)?;
+    if executable {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+            .map_err(|e| format!("chmod {}: {}", path.display(), e))?;
+    }
+    println!("  {} {}", "+".green(), path.display());
+    Ok(())
+}
+
+/// Write a setup file and reference the schema when the repo has one.
+fn write_setup_file(
+    dir: &Path,
+    setup: Value,
+    format: Format,
+    owl_path: &Path,
+) -> Result<PathBuf, String> {
+    let path = dir.join(format.file_name());
+    write_file(&path, &format.render(&setup)?, false)?;
+    if owl_path.join(schema::SCHEMA_FILE).exists() {
+        schema::add_reference(&path, owl_path)?;
+    }
+    Ok(path)
+}
+
+fn install_script(name: &str) -> String {
+    format!(
+        "#!/bin/bash
+
+# Skip installation if {name} is already installed
+if command -v {name} &> /dev/null; then
+    echo \"{name} is already installed. Skipping installation.\"
+    exit 0
+fi
+"
+    )
+}
+
+fn rc_script(name: &str) -> String {
+    format!("# {} shell setup, sourced by bash and zsh\n", name)
+}
+
+fn service_unit(name: &str) -> String {
+    format!(
+        "[Unit]
+Description={name}
+
+[Service]
+Type=simple
+ExecStart=/usr/bin/{name}
+Restart=on-failure
+
+[Install]
+WantedBy=default.target
+"
+    )
+}
+
+// ---------- Setups ----------
+
+pub fn new_setup(name: &str, options: &NewSetupOptions) -> Result<(), String> {
+    let owl_path = get_config().owl_path;
+    check_name(name, &owl_path)?;
+    let dir = owl_path.join("setups").join(name);
+    println!("{} {}", "🐣 New setup".magenta().bold(), name.cyan().bold());
+
+    let mut setup = Map::new();
+    setup.insert("links".to_string(), json!([]));
+    if options.install {
+        write_file(&dir.join("install.sh"), &install_script(name), true)?;
+        setup.insert("install".to_string(), json!("local:install.sh"));
+    }
+    if options.rc {
+        write_file(&dir.join("rc.sh"), &rc_script(name), false)?;
+        setup.insert("rc_scripts".to_string(), json!(["local:rc.sh"]));
+    }
+    if options.service {
+        let unit = format!("systemd/{}.service", name);
+        write_file(&dir.join(&unit), &service_unit(name), false)?;
+        setup.insert(
+            "services".to_string(),
+            json!([{ "path": format!("local:{}", unit), "type": "user" }]),
+        );
+    }
+    write_setup_file(&dir, Value::Object(setup), options.format, &owl_path)?;
+
+    if options.add_to_nest {
+        let Some(nest_file) = get_nest_path() else {
+            return Err("no active nest to add it to; run `owl nest switch`".to_string());
+        };
+        if setup_file::add_dependency(&nest_file, name)? {
+            println!("  {} {}", "+ dependency in".green(), nest_file.display());
+        }
+    }
+    println!(
+        "Fill it in with {}",
+        format!("owl setup {} edit", name).cyan()
+    );
+    Ok(())
+}
+
+// ---------- Nests ----------
+
+fn copy_dir(source: &Path, target: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(target).map_err(|e| format!("create {}: {}", target.display(), e))?;
+    let entries =
+        std::fs::read_dir(source).map_err(|e| format!("read {}: {}", source.display(), e))?;
+    for entry in entries.flatten() {
+        let from = entry.path();
+        let to = target.join(entry.file_name());
+        let file_type = entry
+            .file_type()
+            .map_err(|e| format!("stat {}: {}", from.display(), e))?;
+        if file_type.is_symlink() {
+            let link = std::fs::read_link(&from).map_err(|e| e.to_string())?;
+            std::os::unix::fs::symlink(link, &to)
+                .map_err(|e| format!("link {}: {}", to.display(), e))?;
+        } else if file_type.is_dir() {
+            copy_dir(&from, &to)?;
+            continue;
+        } else {
+            // Keeps permissions, so scripts stay executable
+            std::fs::copy(&from, &to).map_err(|e| format!("copy {}: {}", from.display(), e))?;
+        }
+        println!("  {} {}", "+".green(), to.display());
+    }
+    Ok(())
+}
+
+/// A cloned nest keeps its source's informational `name`; point it at the clone.
+fn rename(file: &Path, from: &str, to: &str) -> Result<(), String> {
+    let format =
+        Format::from_path(file).ok_or_else(|| format!("not a setup file: {}", file.display()))?;
+    let raw = std::fs::read_to_string(file).map_err(|e| e.to_string())?;
+    let value: Value = format.parse(&raw)?;
+    if value.get("name").and_then(Value::as_str) != Some(from) {
+        return Ok(());
+    }
+    let (old, new) = match format {
+        Format::Json => (
+            format!("\"name\": \"{}\"", from),
+            format!("\"name\": \"{}\"", to),
+        ),
+        Format::Toml => (format!("name = \"{}\"", from), format!("name = \"{}\"", to)),
+        Format::Yaml => (format!("name: {}", from), format!("name: {}", to)),
+    };
+    let edited = raw.replacen(&old, &new, 1);
+    let renamed = format
+        .parse::<Value>(&edited)
+        .ok()
+        .and_then(|v| v.get("name")?.as_str().map(str::to_string));
+    if renamed.as_deref() != Some(to) {
+        return Err(format!("update `name` in {} by hand", file.display()));
+    }
+    std::fs::write(file, edited).map_err(|e| format!("write {}: {}", file.display(), e))
+}
+
+/// Create a nest, either empty or as a copy of the nest `from`.
+pub fn new_nest(name: &str, from: Option<&str>, format: Format) -> Result<(), String> {
+    let owl_path = get_config().owl_path;
+    check_name(name, &owl_path)?;
+    let nests = owl_path.join("nests");
+    let dir = nests.join(name);
+    println!("{} {}", "🪺 New nest".magenta().bold(), name.cyan().bold());
+
+    match from {
+        Some(from) => {
+            let source = nests.join(from);
+            if setup_file::find(&source).is_none() {
+                return Err(format!("no nest named '{}'", from));
+            }
+            copy_dir(&source, &dir)?;
+            if let Some(file) = setup_file::find(&dir) {
+                rename(&file, from, name)?;
+            }
+        }
+        None => {
+            write_setup_file(&dir, json!({ "dependencies": [] }), format, &owl_path)?;
+        }
+    }
+    println!("Make it active with {}", "owl nest switch".cyan());
+    Ok(())
+}
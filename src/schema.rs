@@ -0,0 +1,137 @@
+use colored::Colorize;
+use schemars::schema::{RootSchema, Schema, SchemaObject};
+use serde_json::Value;
+use std::path::Path;
+
+use crate::setup_file::{self, Format};
+use crate::{get_config, json, read_setup_headers_from_dir, shell, SetupFileRaw};
+
+// =======================================
+//            Setup Schema
+// =======================================
+//
+// The JSON Schema for setup files is generated from the raw setup types, so it
+// cannot drift from what owl actually parses. `owl schema --write` stores it
+// in the repo and points every setup file at it with a relative reference:
+// a `$schema` key in JSON and the comment taplo or yaml-language-server read
+// in TOML and YAML, so language servers pick it up without editor settings.
+
+pub const SCHEMA_FILE: &str = "setup.schema.json";
+
+pub fn setup_schema() -> RootSchema {
+    let mut schema = schemars::schema_for!(SetupFileRaw);
+    schema.schema.metadata().title = Some("owl setup".to_string());
+    schema
+}
+
+/// Property names of the root object (`None`) or of a definition such as
+/// `SetupFileLinkRaw`; for `"path" | { "path": ... }` entries, those of the
+/// object form.
+pub fn fields(schema: &RootSchema, definition: Option<&str>) -> Vec<String> {
+    let object = match definition {
+        None => Some(&schema.schema),
+        Some(name) => match schema.definitions.get(name) {
+            Some(Schema::Object(object)) => Some(object),
+            _ => None,
+        },
+    };
+    let Some(object) = object else {
+        return Vec::new();
+    };
+    let alternatives = object
+        .subschemas
+        .iter()
+        .flat_map(|s| s.any_of.iter().flatten())
+        .filter_map(|s| match s {
+            Schema::Object(o) => Some(o),
+            Schema::Bool(_) => None,
+        });
+    std::iter::once(object)
+        .chain(alternatives)
+        .filter_map(|o: &SchemaObject| o.object.as_ref())
+        .flat_map(|o| o.properties.keys().cloned())
+        .collect()
+}
+
+pub fn print() {
+    json::print(&setup_schema());
+}
+
+/// Write the schema into the repo and reference it from every setup and nest.
+pub fn write() {
+    let owl_path = get_config().owl_path;
+    let schema_path = owl_path.join(SCHEMA_FILE);
+    let contents = match serde_json::to_string_pretty(&setup_schema()) {
+        Ok(contents) => contents + "\n",
+        Err(e) => {
+            eprintln!("Unable to serialize schema: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = shell::write_generated(&schema_path, &contents) {
+        eprintln!("{} {}", "Unable to write schema:".red(), e);
+        std::process::exit(1);
+    }
+    println!("{} {}", "✓ Wrote".green(), schema_path.display());
+
+    let mut updated = 0;
+    for dir in ["setups", "nests"] {
+        for header in read_setup_headers_from_dir(&owl_path.join(dir)) {
+            match add_reference(&header.setup_file_path, &owl_path) {
+                Ok(true) => {
+                    updated += 1;
+                    println!(
+                        "  {} {}",
+                        "+ schema".green(),
+                        header.setup_file_path.display()
+                    );
+                }
+                Ok(false) => {}
+                Err(e) => eprintln!(
+                    "  {} {}: {}",
+                    "✗".red(),
+                    header.setup_file_path.display(),
+                    e
+                ),
+            }
+        }
+    }
+    println!(
+        "Added a schema reference to {} setup files",
+        updated.to_string().bold()
+    );
+}
+
+/// Reference the schema from `setup_file`, leaving the rest of the file as
+/// written. Returns false when it already has a reference.
+pub fn add_reference(setup_file: &Path, owl_path: &Path) -> Result<bool, String> {
+    let format = Format::from_path(setup_file)
+        .ok_or_else(|| format!("not a setup file: {}", setup_file.display()))?;
+    let raw = std::fs::read_to_string(setup_file).map_err(|e| e.to_string())?;
+    let value: Value = format.parse(&raw)?;
+    let Some(object) = value.as_object() else {
+        return Err("not an object".to_string());
+    };
+    let directive = match format {
+        Format::Json => None,
+        Format::Toml => Some("#:schema "),
+        Format::Yaml => Some("# yaml-language-server: $schema="),
+    };
+    if object.contains_key("$schema") || directive.is_some_and(|d| raw.contains(d)) {
+        return Ok(false);
+    }
+
+    let depth = setup_file
+        .parent()
+        .and_then(|dir| dir.strip_prefix(owl_path).ok())
+        .map(|dir| dir.components().count())
+        .ok_or_else(|| "not inside the owl repository".to_string())?;
+    let reference = format!("{}{}", "../".repeat(depth), SCHEMA_FILE);
+
+    let inserted = match directive {
+        Some(directive) => format!("{}{}\n{}", directive, reference, raw),
+        None => setup_file::insert_json_key(&raw, "$schema", &serde_json::json!(reference))?,
+    };
+    std::fs::write(setup_file, inserted).map_err(|e| e.to_string())?;
+    Ok(true)
+}
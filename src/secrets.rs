@@ -0,0 +1,253 @@
+use colored::Colorize;
+use regex::Regex;
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::{ensure_exists, replace_tokens, tilde_expand, tilde_expand_path, SetupSecretRaw};
+
+// =======================================
+//            Secrets
+// =======================================
+//
+// A secret is a committed template holding references (e.g. `op://Dev/Npm/credential`)
+// that a provider resolves into an output file with mode 0600. Outputs are
+// never committed; links and rc scripts that use them wait until they exist.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProviderKind {
+    /// 1Password CLI, `op://vault/item/field` references
+    OnePassword,
+    /// passwordstore.org, `pass://path/to/entry` references (first line of the entry)
+    Pass,
+    /// Local `KEY=VALUE` file (optionally gpg encrypted), `file://KEY` references
+    File,
+}
+
+impl ProviderKind {
+    fn from_str_or_default(v: Option<&str>) -> Result<Self, String> {
+        match v.map(|p| p.to_lowercase()).as_deref() {
+            None | Some("op") => Ok(ProviderKind::OnePassword),
+            Some("pass") => Ok(ProviderKind::Pass),
+            Some("file") => Ok(ProviderKind::File),
+            Some(other) => Err(format!(
+                "invalid secret provider '{}' (expected op, pass or file)",
+                other
+            )),
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ProviderKind::OnePassword => "op",
+            ProviderKind::Pass => "pass",
+            ProviderKind::File => "file",
+        }
+    }
+
+    fn provider(&self) -> Box<dyn SecretProvider> {
+        match self {
+            ProviderKind::OnePassword => Box::new(OnePassword),
+            ProviderKind::Pass => Box::new(Pass),
+            ProviderKind::File => Box::new(LocalFile {
+                path: local_secrets_path(),
+            }),
+        }
+    }
+}
+
+pub trait SecretProvider {
+    /// Resolve every reference in `template`.
+    fn render(&self, template: &str) -> Result<String, String>;
+}
+
+fn run_with_stdin(cmd: &mut Command, input: &str) -> Result<String, String> {
+    let program = cmd.get_program().to_string_lossy().to_string();
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("exec {}: {}", program, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(input.as_bytes())
+            .map_err(|e| format!("write to {}: {}", program, e))?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("wait for {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Replace every `<scheme>://<reference>` in `template` using `resolve`.
+fn replace_references(
+    template: &str,
+    scheme: &str,
+    mut resolve: impl FnMut(&str) -> Result<String, String>,
+) -> Result<String, String> {
+    let re = Regex::new(&format!(r"{}://([A-Za-z0-9_./@+-]+)", scheme)).expect("valid regex");
+    let mut output = String::with_capacity(template.len());
+    let mut last = 0;
+    for caps in re.captures_iter(template) {
+        let whole = caps.get(0).expect("match");
+        output.push_str(&template[last..whole.start()]);
+        output.push_str(&resolve(&caps[1])?);
+        last = whole.end();
+    }
+    output.push_str(&template[last..]);
+    Ok(output)
+}
+
+struct OnePassword;
+
+impl SecretProvider for OnePassword {
+    fn render(&self, template: &str) -> Result<String, String> {
+        run_with_stdin(Command::new("op").arg("inject"), template)
+    }
+}
+
+struct Pass;
+
+impl SecretProvider for Pass {
+    fn render(&self, template: &str) -> Result<String, String> {
+        replace_references(template, "pass", |entry| {
+            let shown = run_with_stdin(Command::new("pass").arg("show").arg(entry), "")?;
+            Ok(shown.lines().next().unwrap_or_default().to_string())
+        })
+    }
+}
+
+fn local_secrets_path() -> PathBuf {
+    match std::env::var("OWL_SECRETS_FILE") {
+        Ok(path) => tilde_expand_path(&path),
+        Err(_) => tilde_expand_path("~/.config/owl/secrets.env"),
+    }
+}
+
+struct LocalFile {
+    path: PathBuf,
+}
+
+impl LocalFile {
+    fn read(&self) -> Result<String, String> {
+        let is_encrypted = self.path.extension().is_some_and(|e| e == "gpg");
+        if is_encrypted {
+            return run_with_stdin(
+                Command::new("gpg")
+                    .args(["--quiet", "--decrypt"])
+                    .arg(&self.path),
+                "",
+            );
+        }
+        std::fs::read_to_string(&self.path)
+            .map_err(|e| format!("read {}: {}", self.path.display(), e))
+    }
+
+    fn lookup(contents: &str, key: &str) -> Option<String> {
+        contents.lines().find_map(|line| {
+            let line = line.trim();
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (k, v) = line.split_once('=')?;
+            (k.trim() == key).then(|| v.trim().trim_matches('"').trim_matches('\'').to_string())
+        })
+    }
+}
+
+impl SecretProvider for LocalFile {
+    fn render(&self, template: &str) -> Result<String, String> {
+        // Only read (and possibly decrypt) the file when the template needs it
+        let mut contents: Option<String> = None;
+        replace_references(template, "file", |key| {
+            if contents.is_none() {
+                contents = Some(self.read()?);
+            }
+            Self::lookup(contents.as_deref().unwrap_or_default(), key)
+                .ok_or_else(|| format!("{} has no entry for {}", self.path.display(), key))
+        })
+    }
+}
+
+pub struct ValidatedSecret {
+    pub template: PathBuf,
+    pub output: PathBuf,
+    pub provider: ProviderKind,
+}
+
+impl ValidatedSecret {
+    pub fn make(raw: &SetupSecretRaw, setup_dir: &Path) -> Result<Self, String> {
+        let template = replace_tokens(&tilde_expand(&raw.template), "", setup_dir);
+        ensure_exists(&template)?;
+        let output = replace_tokens(&tilde_expand(&raw.output), "", setup_dir);
+        if output == template {
+            return Err(format!(
+                "secret output would overwrite its template: {}",
+                output.display()
+            ));
+        }
+        let provider = ProviderKind::from_str_or_default(raw.provider.as_deref())?;
+        Ok(Self {
+            template,
+            output,
+            provider,
+        })
+    }
+
+    pub fn is_rendered(&self) -> bool {
+        self.output.exists()
+    }
+
+    /// Permission bits of the rendered output, if it exists.
+    pub fn output_mode(&self) -> Option<u32> {
+        std::fs::metadata(&self.output)
+            .ok()
+            .map(|m| m.permissions().mode() & 0o777)
+    }
+
+    pub fn render(&self) -> Result<(), String> {
+        let template = std::fs::read_to_string(&self.template)
+            .map_err(|e| format!("read {}: {}", self.template.display(), e))?;
+        let rendered = self.provider.provider().render(&template)?;
+        write_private(&self.output, rendered.as_bytes())
+    }
+}
+
+/// Atomically write `contents` to `path`, readable by the owner only.
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("create {}: {}", parent.display(), e))?;
+    }
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let tmp_path = path.with_file_name(format!(".{}.owl-tmp", file_name));
+    let _ = std::fs::remove_file(&tmp_path);
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&tmp_path)
+        .map_err(|e| format!("create {}: {}", tmp_path.display(), e))?;
+    file.write_all(contents)
+        .map_err(|e| format!("write {}: {}", tmp_path.display(), e))?;
+    std::fs::rename(&tmp_path, path).map_err(|e| format!("write {}: {}", path.display(), e))
+}
+
+pub fn print_secret_result(secret: &ValidatedSecret, result: &Result<(), String>) {
+    let template = secret.template.display().to_string().blue();
+    let output = secret.output.display().to_string().green();
+    match result {
+        Ok(()) => println!("    {} ⇒ {} ✅", template, output),
+        Err(e) => println!("    {} ⇒ {} ❌ {}", template, output, e),
+    }
+}
@@ -0,0 +1,709 @@
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+// =======================================
+//            Setup File Formats
+// =======================================
+//
+// A setup directory holds exactly one of setup.json, setup.toml or
+// setup.yaml. All three parse into the same raw types; TOML and YAML exist so
+// entries can carry comments explaining why they are there.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    pub const ALL: [Format; 3] = [Format::Json, Format::Toml, Format::Yaml];
+
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "json" => Ok(Format::Json),
+            "toml" => Ok(Format::Toml),
+            "yaml" | "yml" => Ok(Format::Yaml),
+            other => Err(format!(
+                "invalid format '{}' (expected json, toml or yaml)",
+                other
+            )),
+        }
+    }
+
+    /// The format of a setup file, judged by its file name.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        Format::ALL.into_iter().find(|f| f.file_name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Json => "JSON",
+            Format::Toml => "TOML",
+            Format::Yaml => "YAML",
+        }
+    }
+
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            Format::Json => "setup.json",
+            Format::Toml => "setup.toml",
+            Format::Yaml => "setup.yaml",
+        }
+    }
+
+    pub fn parse<T: DeserializeOwned>(&self, raw: &str) -> Result<T, String> {
+        match self {
+            Format::Json => serde_json::from_str(raw).map_err(|e| e.to_string()),
+            Format::Toml => toml::from_str(raw).map_err(|e| e.to_string().trim_end().to_string()),
+            Format::Yaml => serde_yaml::from_str(raw).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Serialize a parsed setup file. Nulls are dropped first since TOML has
+    /// no null and an absent field means the same thing.
+    pub fn render(&self, value: &Value) -> Result<String, String> {
+        let value = without_nulls(value);
+        match self {
+            Format::Json => serde_json::to_string_pretty(&value)
+                .map(|s| s + "\n")
+                .map_err(|e| e.to_string()),
+            Format::Toml => toml::to_string_pretty(&value).map_err(|e| e.to_string()),
+            Format::Yaml => serde_yaml::to_string(&value).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+fn without_nulls(value: &Value) -> Value {
+    match value {
+        Value::Object(object) => Value::Object(
+            object
+                .iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| (k.clone(), without_nulls(v)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(without_nulls).collect()),
+        other => other.clone(),
+    }
+}
+
+/// Every setup file in `dir`; more than one is a validation error.
+pub fn find_all(dir: &Path) -> Vec<PathBuf> {
+    Format::ALL
+        .iter()
+        .map(|f| dir.join(f.file_name()))
+        .filter(|p| p.is_file())
+        .collect()
+}
+
+/// The setup file in `dir`, if any.
+pub fn find(dir: &Path) -> Option<PathBuf> {
+    find_all(dir).into_iter().next()
+}
+
+/// Read and parse a setup file in whichever format its name says.
+pub fn read<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let format =
+        Format::from_path(path).ok_or_else(|| format!("not a setup file: {}", path.display()))?;
+    let raw = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    format.parse(&raw)
+}
+
+/// Rewrite the setup file at `path` in another format and remove the old
+/// file. Comments in TOML or YAML do not survive, and keys come out sorted.
+pub fn convert(path: &Path, to: Format) -> Result<PathBuf, String> {
+    let from =
+        Format::from_path(path).ok_or_else(|| format!("not a setup file: {}", path.display()))?;
+    if from == to {
+        return Err(format!("{} is already {}", path.display(), to.name()));
+    }
+    let mut value: Value = read(path)?;
+    // TOML and YAML editors find the schema through a comment instead
+    if let Some(object) = value.as_object_mut() {
+        object.remove("$schema");
+    }
+    let rendered = to.render(&value)?;
+    // Make sure nothing was lost before replacing the original
+    if to.parse::<Value>(&rendered)? != without_nulls(&value) {
+        return Err(format!("{} output does not round-trip", to.name()));
+    }
+
+    let target = path.with_file_name(to.file_name());
+    std::fs::write(&target, rendered).map_err(|e| format!("write {}: {}", target.display(), e))?;
+    std::fs::remove_file(path).map_err(|e| format!("remove {}: {}", path.display(), e))?;
+    Ok(target)
+}
+
+/// Insert `key` as the first key of a JSON object, leaving the rest as written.
+pub fn insert_json_key(raw: &str, key: &str, value: &Value) -> Result<String, String> {
+    let open = raw
+        .find('{')
+        .ok_or_else(|| "not a JSON object".to_string())?;
+    let (head, rest) = raw.split_at(open + 1);
+    let entry = format!("{}: {}", Value::from(key), inline(value, Format::Json));
+    Ok(if rest.trim_start().starts_with('}') {
+        format!("{}{}{}", head, entry, rest.trim_start())
+    } else if let Some(body) = rest.strip_prefix('\n') {
+        let indent: String = body.chars().take_while(|c| c.is_whitespace()).collect();
+        format!("{}\n{}{},\n{}", head, indent, entry, body)
+    } else {
+        format!("{}{}, {}", head, entry, rest.trim_start())
+    })
+}
+
+// ---------- Appending entries ----------
+//
+// `owl setup new --add-to-nest` and `owl adopt` append to a list in a setup
+// file by editing its text, so comments and key order survive. Every edit is
+// parsed back and only written if it is the original plus the new entry.
+
+/// `item` written on one line: JSON, which YAML reads as a flow node, or a
+/// TOML inline table or array.
+fn inline(item: &Value, format: Format) -> String {
+    match (format, item) {
+        (Format::Toml, Value::Object(object)) => {
+            let fields: Vec<String> = object
+                .iter()
+                .map(|(k, v)| format!("{} = {}", k, inline(v, format)))
+                .collect();
+            format!("{{ {} }}", fields.join(", "))
+        }
+        (Format::Toml, Value::Array(items)) => {
+            let items: Vec<String> = items.iter().map(|v| inline(v, format)).collect();
+            format!("[{}]", items.join(", "))
+        }
+        // `{"key": value}` rather than serde's `{"key":value}`, as people write it
+        (_, Value::Object(object)) => {
+            let fields: Vec<String> = object
+                .iter()
+                .map(|(k, v)| format!("{}: {}", Value::from(k.as_str()), inline(v, format)))
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+        (_, Value::Array(items)) => {
+            let items: Vec<String> = items.iter().map(|v| inline(v, format)).collect();
+            format!("[{}]", items.join(", "))
+        }
+        _ => item.to_string(),
+    }
+}
+
+/// Offset just past the `"field"` key of the outermost JSON object, skipping
+/// keys of nested objects and strings that happen to read the same.
+fn find_json_key(raw: &str, field: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut chars = raw.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '{' | '[' => depth += 1,
+            '}' | ']' => depth -= 1,
+            '"' => {
+                let mut escaped = false;
+                let (end, _) = chars.by_ref().find(|&(_, c)| {
+                    let closes = !escaped && c == '"';
+                    escaped = !escaped && c == '\\';
+                    closes
+                })?;
+                let is_key = raw[end + 1..].trim_start().starts_with(':');
+                if depth == 1 && is_key && &raw[i + 1..end] == field {
+                    return Some(end + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Offset just past the `=` or `:` of a top-level `field` line. TOML keys
+/// after the first table header belong to that table, so the search stops there.
+fn find_line_key(raw: &str, format: Format, field: &str) -> Option<usize> {
+    let mut offset = 0;
+    for line in raw.lines() {
+        if format == Format::Toml && line.starts_with('[') {
+            return None;
+        }
+        let found = line
+            .strip_prefix(field)
+            .is_some_and(|rest| rest.trim_start().starts_with(['=', ':']));
+        if found {
+            return Some(offset + line.find(['=', ':'])? + 1);
+        }
+        offset += line.len() + 1;
+    }
+    None
+}
+
+/// Byte offsets of the `[` and `]` of the top-level `field` array written in
+/// bracket form.
+fn find_array(raw: &str, format: Format, field: &str) -> Option<(usize, usize)> {
+    let key = match format {
+        Format::Json => find_json_key(raw, field)?,
+        Format::Toml | Format::Yaml => find_line_key(raw, format, field)?,
+    };
+    let after = &raw[key..];
+    let open = key + after.find(|c: char| !c.is_whitespace() && c != ':')?;
+    if raw[open..].chars().next()? != '[' {
+        return None;
+    }
+
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (i, c) in raw[open..].char_indices() {
+        match quote {
+            Some(q) => {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' && q == '"' {
+                    escaped = true;
+                } else if c == q {
+                    quote = None;
+                }
+            }
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '[' | '{' => depth += 1,
+                ']' | '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some((open, open + i));
+                    }
+                }
+                _ => {}
+            },
+        }
+    }
+    None
+}
+
+/// Append `item` to the array spanning `open..=close`, following its layout.
+fn append_to_array(raw: &str, open: usize, close: usize, item: &Value, format: Format) -> String {
+    let body = raw[open + 1..close].trim_end();
+    let end = open + 1 + body.len();
+    if body.trim().is_empty() {
+        return format!(
+            "{}{}{}",
+            &raw[..open + 1],
+            inline(item, format),
+            &raw[close..]
+        );
+    }
+    let (separator, text) = if body.contains('\n') {
+        let last_line = body.rsplit('\n').next().unwrap_or_default();
+        let indent: String = last_line
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .collect();
+        let text = match format {
+            // Objects in a multi-line JSON array get their own block
+            Format::Json => serde_json::to_string_pretty(item)
+                .unwrap_or_else(|_| item.to_string())
+                .replace('\n', &format!("\n{}", indent)),
+            _ => inline(item, format),
+        };
+        (format!("\n{}", indent), text)
+    } else {
+        (" ".to_string(), inline(item, format))
+    };
+    let addition = if body.ends_with(',') {
+        format!("{}{},", separator, text)
+    } else {
+        format!(",{}{}", separator, text)
+    };
+    format!("{}{}{}", &raw[..end], addition, &raw[end..])
+}
+
+/// Append `item` to a YAML block sequence under a top-level `field:`.
+fn append_to_yaml_list(raw: &str, field: &str, item: &Value) -> Option<String> {
+    let lines: Vec<&str> = raw.lines().collect();
+    let key = lines
+        .iter()
+        .position(|l| l.trim_end() == format!("{}:", field))?;
+    let block = lines[key + 1..]
+        .iter()
+        .take_while(|l| l.starts_with(' ') || l.starts_with('-'))
+        .count();
+    let first = lines.get(key + 1)?;
+    if block == 0 || !first.trim_start().starts_with('-') {
+        return None;
+    }
+    let indent: String = first.chars().take_while(|c| *c == ' ').collect();
+    let mut out: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+    out.insert(
+        key + block + 1,
+        format!("{}- {}", indent, inline(item, Format::Yaml)),
+    );
+    Some(out.join("\n") + "\n")
+}
+
+/// The text of a setup file with `item` appended to its top-level `field` list.
+fn append_text(
+    raw: &str,
+    value: &Value,
+    format: Format,
+    field: &str,
+    item: &Value,
+) -> Option<String> {
+    if let Some((open, close)) = find_array(raw, format, field) {
+        return Some(append_to_array(raw, open, close, item, format));
+    }
+    let present = value.get(field).is_some();
+    match format {
+        Format::Json if !present => {
+            insert_json_key(raw, field, &Value::Array(vec![item.clone()])).ok()
+        }
+        Format::Json => None,
+        // An array of tables, or a new one; either way `[[field]]` at the end adds to it
+        Format::Toml if item.is_object() => Some(format!(
+            "{}{}\n[[{}]]\n{}\n",
+            raw,
+            if raw.ends_with('\n') || raw.is_empty() {
+                ""
+            } else {
+                "\n"
+            },
+            field,
+            item.as_object()?
+                .iter()
+                .map(|(k, v)| format!("{} = {}", k, inline(v, format)))
+                .collect::<Vec<_>>()
+                .join("\n")
+        )),
+        // Before any table, but after leading comments such as `#:schema`
+        Format::Toml if !present => {
+            let split: usize = raw
+                .lines()
+                .take_while(|l| l.starts_with('#'))
+                .map(|l| l.len() + 1)
+                .sum::<usize>()
+                .min(raw.len());
+            Some(format!(
+                "{}{} = [{}]\n{}",
+                &raw[..split],
+                field,
+                inline(item, format),
+                &raw[split..]
+            ))
+        }
+        Format::Toml => None,
+        Format::Yaml if present => append_to_yaml_list(raw, field, item),
+        // Indent the list the way the file's other block lists are
+        Format::Yaml => Some(format!(
+            "{}{}{}:\n{}- {}\n",
+            raw,
+            if raw.ends_with('\n') || raw.is_empty() {
+                ""
+            } else {
+                "\n"
+            },
+            field,
+            if raw.starts_with("- ") || raw.contains("\n- ") {
+                ""
+            } else {
+                "  "
+            },
+            inline(item, format)
+        )),
+    }
+}
+
+/// `raw` with `item` appended to its top-level `field` list, provided the
+/// edit parses back as exactly the original plus `item`.
+fn appended(raw: &str, format: Format, field: &str, item: &Value) -> Option<String> {
+    let value: Value = format.parse(raw).ok()?;
+    let mut expected = value.clone();
+    expected
+        .as_object_mut()?
+        .entry(field)
+        .or_insert_with(|| Value::Array(Vec::new()))
+        .as_array_mut()?
+        .push(item.clone());
+    let edited = append_text(raw, &value, format, field, item)?;
+    (format.parse::<Value>(&edited).ok()? == expected).then_some(edited)
+}
+
+/// Append `item` to the top-level `field` list of the setup file at `path`.
+/// Layouts this does not understand are left alone with an error.
+pub fn append_entry(path: &Path, field: &str, item: &Value) -> Result<(), String> {
+    let format =
+        Format::from_path(path).ok_or_else(|| format!("not a setup file: {}", path.display()))?;
+    let raw = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    // Report a file that does not parse as such, not as a layout to edit by hand
+    format.parse::<Value>(&raw)?;
+    let edited = appended(&raw, format, field, item)
+        .ok_or_else(|| format!("add {} to `{}` in {} by hand", item, field, path.display()))?;
+    std::fs::write(path, edited).map_err(|e| format!("write {}: {}", path.display(), e))
+}
+
+/// Add `name` to the `dependencies` of the setup file at `path`. Returns false
+/// when it is already listed.
+pub fn add_dependency(path: &Path, name: &str) -> Result<bool, String> {
+    let value: Value = read(path)?;
+    let listed = value
+        .get("dependencies")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .any(|dep| dep.as_str().or_else(|| dep.get("name")?.as_str()) == Some(name));
+    if listed {
+        return Ok(false);
+    }
+    append_entry(path, "dependencies", &Value::from(name))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn link() -> Value {
+        json!({ "source": "local:bar.conf", "target": "~/.config/foo/bar.conf" })
+    }
+
+    /// Append `item` to `field`, failing the test if the edit is refused.
+    fn append(raw: &str, format: Format, field: &str, item: &Value) -> String {
+        appended(raw, format, field, item)
+            .unwrap_or_else(|| panic!("refused to append {} to `{}` in:\n{}", item, field, raw))
+    }
+
+    #[test]
+    fn json_key_goes_first() {
+        let reference = json!("../setup.schema.json");
+        assert_eq!(
+            insert_json_key("{}", "$schema", &reference).unwrap(),
+            r#"{"$schema": "../setup.schema.json"}"#
+        );
+        assert_eq!(
+            insert_json_key(r#"{"links": []}"#, "$schema", &reference).unwrap(),
+            r#"{"$schema": "../setup.schema.json", "links": []}"#
+        );
+        assert_eq!(
+            insert_json_key("{\n    \"links\": []\n}\n", "$schema", &reference).unwrap(),
+            "{\n    \"$schema\": \"../setup.schema.json\",\n    \"links\": []\n}\n"
+        );
+        assert!(insert_json_key("[]", "$schema", &reference).is_err());
+    }
+
+    #[test]
+    fn json_empty_array() {
+        assert_eq!(
+            append(r#"{"links": []}"#, Format::Json, "links", &link()),
+            r#"{"links": [{"source": "local:bar.conf", "target": "~/.config/foo/bar.conf"}]}"#
+        );
+        assert_eq!(
+            append(
+                "{\n  \"dependencies\": [ ]\n}\n",
+                Format::Json,
+                "dependencies",
+                &json!("git")
+            ),
+            "{\n  \"dependencies\": [\"git\"]\n}\n"
+        );
+    }
+
+    #[test]
+    fn json_single_line_array() {
+        assert_eq!(
+            append(
+                r#"{"dependencies": ["git", "zsh"]}"#,
+                Format::Json,
+                "dependencies",
+                &json!("fd")
+            ),
+            r#"{"dependencies": ["git", "zsh", "fd"]}"#
+        );
+    }
+
+    #[test]
+    fn json_multi_line_array() {
+        let raw = "{\n  \"dependencies\": [\n    \"git\",\n    \"zsh\"\n  ]\n}\n";
+        assert_eq!(
+            append(raw, Format::Json, "dependencies", &json!("fd")),
+            "{\n  \"dependencies\": [\n    \"git\",\n    \"zsh\",\n    \"fd\"\n  ]\n}\n"
+        );
+        let raw = "{\n  \"links\": [\n    {\n      \"source\": \"local:a\",\n      \"target\": \"~/a\"\n    }\n  ]\n}\n";
+        assert_eq!(
+            append(raw, Format::Json, "links", &link()),
+            "{\n  \"links\": [\n    {\n      \"source\": \"local:a\",\n      \"target\": \"~/a\"\n    },\n    {\n      \"source\": \"local:bar.conf\",\n      \"target\": \"~/.config/foo/bar.conf\"\n    }\n  ]\n}\n"
+        );
+    }
+
+    #[test]
+    fn json_nested_objects() {
+        // The same name as a nested key or a string value is not the field
+        let raw = r#"{"vars": {"links": "x"}, "services": [{"path": "links", "when": {"env": ["A"]}}], "links": [{"source": "local:a]", "target": "~/a", "when": {"env": ["B"]}}]}"#;
+        assert_eq!(
+            append(raw, Format::Json, "links", &link()),
+            r#"{"vars": {"links": "x"}, "services": [{"path": "links", "when": {"env": ["A"]}}], "links": [{"source": "local:a]", "target": "~/a", "when": {"env": ["B"]}}, {"source": "local:bar.conf", "target": "~/.config/foo/bar.conf"}]}"#
+        );
+    }
+
+    #[test]
+    fn json_missing_key() {
+        assert_eq!(
+            append(
+                "{\n  \"install\": \"local:install.sh\"\n}\n",
+                Format::Json,
+                "links",
+                &link()
+            ),
+            "{\n  \"links\": [{\"source\": \"local:bar.conf\", \"target\": \"~/.config/foo/bar.conf\"}],\n  \"install\": \"local:install.sh\"\n}\n"
+        );
+        assert_eq!(
+            append("{}", Format::Json, "dependencies", &json!("git")),
+            r#"{"dependencies": ["git"]}"#
+        );
+    }
+
+    #[test]
+    fn toml_arrays() {
+        assert_eq!(
+            append(
+                "dependencies = []\n",
+                Format::Toml,
+                "dependencies",
+                &json!("git")
+            ),
+            "dependencies = [\"git\"]\n"
+        );
+        assert_eq!(
+            append(
+                "dependencies = [\"git\"]\n",
+                Format::Toml,
+                "dependencies",
+                &json!("fd")
+            ),
+            "dependencies = [\"git\", \"fd\"]\n"
+        );
+        assert_eq!(
+            append(
+                "links = [{ source = \"local:a\", target = \"~/a\" }]\n",
+                Format::Toml,
+                "links",
+                &link()
+            ),
+            "links = [{ source = \"local:a\", target = \"~/a\" }, { source = \"local:bar.conf\", target = \"~/.config/foo/bar.conf\" }]\n"
+        );
+    }
+
+    #[test]
+    fn toml_trailing_comma() {
+        let raw = "dependencies = [\n  \"git\", # version control\n  \"zsh\",\n]\n";
+        assert_eq!(
+            append(raw, Format::Toml, "dependencies", &json!("fd")),
+            "dependencies = [\n  \"git\", # version control\n  \"zsh\",\n  \"fd\",\n]\n"
+        );
+    }
+
+    #[test]
+    fn toml_array_of_tables() {
+        let raw = "# editor\n[[links]]\nsource = \"local:a\"\ntarget = \"~/a\"\n\n[vars]\nlinks = \"x\"\n";
+        assert_eq!(
+            append(raw, Format::Toml, "links", &link()),
+            format!(
+                "{}\n[[links]]\nsource = \"local:bar.conf\"\ntarget = \"~/.config/foo/bar.conf\"\n",
+                raw
+            )
+        );
+    }
+
+    #[test]
+    fn toml_missing_key() {
+        let raw = "#:schema ../../setup.schema.json\n# Shell setup\ninstall = \"local:install.sh\"\n\n[vars]\nemail = \"a@x\"\n";
+        assert_eq!(
+            append(raw, Format::Toml, "dependencies", &json!("git")),
+            "#:schema ../../setup.schema.json\n# Shell setup\ndependencies = [\"git\"]\ninstall = \"local:install.sh\"\n\n[vars]\nemail = \"a@x\"\n"
+        );
+        assert_eq!(
+            append(raw, Format::Toml, "links", &link()),
+            format!(
+                "{}\n[[links]]\nsource = \"local:bar.conf\"\ntarget = \"~/.config/foo/bar.conf\"\n",
+                raw
+            )
+        );
+    }
+
+    #[test]
+    fn yaml_block_lists() {
+        let raw = "dependencies:\n  - git # version control\n  - zsh\ninstall: local:install.sh\n";
+        assert_eq!(
+            append(raw, Format::Yaml, "dependencies", &json!("fd")),
+            "dependencies:\n  - git # version control\n  - zsh\n  - \"fd\"\ninstall: local:install.sh\n"
+        );
+        let raw = "links:\n- source: local:a\n  target: ~/a\n";
+        assert_eq!(
+            append(raw, Format::Yaml, "links", &link()),
+            "links:\n- source: local:a\n  target: ~/a\n- {\"source\": \"local:bar.conf\", \"target\": \"~/.config/foo/bar.conf\"}\n"
+        );
+    }
+
+    #[test]
+    fn yaml_flow_list() {
+        assert_eq!(
+            append(
+                "dependencies: [git, zsh]\n",
+                Format::Yaml,
+                "dependencies",
+                &json!("fd")
+            ),
+            "dependencies: [git, zsh, \"fd\"]\n"
+        );
+        assert_eq!(
+            append(
+                "dependencies: []\n",
+                Format::Yaml,
+                "dependencies",
+                &json!("fd")
+            ),
+            "dependencies: [\"fd\"]\n"
+        );
+    }
+
+    #[test]
+    fn yaml_missing_key() {
+        assert_eq!(
+            append("install: local:install.sh\n", Format::Yaml, "links", &link()),
+            "install: local:install.sh\nlinks:\n  - {\"source\": \"local:bar.conf\", \"target\": \"~/.config/foo/bar.conf\"}\n"
+        );
+        assert_eq!(
+            append(
+                "services:\n- path: local:a.service\n",
+                Format::Yaml,
+                "dependencies",
+                &json!("git")
+            ),
+            "services:\n- path: local:a.service\ndependencies:\n- \"git\"\n"
+        );
+    }
+
+    #[test]
+    fn non_lists_are_refused() {
+        assert_eq!(
+            appended(r#"{"links": {}}"#, Format::Json, "links", &link()),
+            None
+        );
+        assert_eq!(
+            appended(
+                "dependencies = \"git\"\n",
+                Format::Toml,
+                "dependencies",
+                &json!("fd")
+            ),
+            None
+        );
+        assert_eq!(
+            appended(
+                "dependencies: git\n",
+                Format::Yaml,
+                "dependencies",
+                &json!("fd")
+            ),
+            None
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use std::path::Path;
+
+// =======================================
+//            Shell Rendering
+// =======================================
+//
+// Helpers for the shell files owl generates. Values may reference other
+// variables as `$NAME`, `${NAME}` or a leading `~`; each output format keeps
+// those references live instead of baking in owl's own environment.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Nu,
+}
+
+impl Shell {
+    pub const ALL: [Shell; 4] = [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::Nu];
+
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            "nu" | "nushell" => Ok(Shell::Nu),
+            other => Err(format!(
+                "invalid shell '{}' (expected bash, zsh, fish or nu)",
+                other
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+            Shell::Nu => "nu",
+        }
+    }
+}
+
+/// The shell an rc script is written for. Plain `sh` scripts run in both bash and zsh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ScriptShell {
+    Sh,
+    Only(Shell),
+}
+
+impl ScriptShell {
+    /// Use `name` when given, otherwise infer from the extension (`.bash`, `.zsh`,
+    /// `.fish`, `.nu`); anything else, e.g. `.sh` or `.shenv`, is `sh`.
+    pub fn from_name_or_path(name: Option<&str>, path: &Path) -> Result<Self, String> {
+        match name {
+            Some("sh") => Ok(ScriptShell::Sh),
+            Some(name) => Shell::from_name(name).map(ScriptShell::Only),
+            None => Ok(path
+                .extension()
+                .and_then(|e| e.to_str())
+                .and_then(|e| Shell::from_name(e).ok())
+                .map(ScriptShell::Only)
+                .unwrap_or(ScriptShell::Sh)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScriptShell::Sh => "sh",
+            ScriptShell::Only(shell) => shell.name(),
+        }
+    }
+
+    pub fn runs_in(&self, shell: Shell) -> bool {
+        match self {
+            ScriptShell::Sh => matches!(shell, Shell::Bash | Shell::Zsh),
+            ScriptShell::Only(only) => *only == shell,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    Var(String),
+}
+
+fn parse_value(value: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut rest = value;
+    if let Some(after) = rest.strip_prefix('~') {
+        if after.is_empty() || after.starts_with('/') {
+            segments.push(Segment::Var("HOME".to_string()));
+            rest = after;
+        }
+    }
+    let mut chars = rest.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            literal.push(c);
+            continue;
+        }
+        let braced = chars.peek() == Some(&'{');
+        if braced {
+            chars.next();
+        }
+        let mut name = String::new();
+        while let Some(&n) = chars.peek() {
+            if n.is_ascii_alphanumeric() || n == '_' {
+                name.push(n);
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        if braced && chars.peek() == Some(&'}') {
+            chars.next();
+        }
+        if name.is_empty() {
+            literal.push('$');
+            continue;
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(std::mem::take(&mut literal)));
+        }
+        segments.push(Segment::Var(name));
+    }
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+    segments
+}
+
+fn escape(literal: &str, special: &[char]) -> String {
+    let mut out = String::with_capacity(literal.len());
+    for c in literal.chars() {
+        if special.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Double-quoted POSIX shell string (bash, zsh). systemd `environment.d`
+/// files read the same syntax.
+pub fn sh_value(value: &str) -> String {
+    let body: String = parse_value(value)
+        .into_iter()
+        .map(|s| match s {
+            Segment::Literal(l) => escape(&l, &['"', '\\', '`', '$']),
+            Segment::Var(v) => format!("${{{}}}", v),
+        })
+        .collect();
+    format!("\"{}\"", body)
+}
+
+/// Double-quoted fish string.
+pub fn fish_value(value: &str) -> String {
+    let body: String = parse_value(value)
+        .into_iter()
+        .map(|s| match s {
+            Segment::Literal(l) => escape(&l, &['"', '\\', '$']),
+            Segment::Var(v) => format!("{{${}}}", v),
+        })
+        .collect();
+    format!("\"{}\"", body)
+}
+
+/// Nushell interpolated string; unset variables render as empty.
+pub fn nu_value(value: &str) -> String {
+    let body: String = parse_value(value)
+        .into_iter()
+        .map(|s| match s {
+            Segment::Literal(l) => escape(&l, &['"', '\\', '(', ')']),
+            Segment::Var(v) => format!("($env.{}? | default \"\")", v),
+        })
+        .collect();
+    format!("$\"{}\"", body)
+}
+
+/// Single-quoted POSIX shell string; nothing inside is expanded.
+pub fn sh_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Single-quoted fish string; nothing inside is expanded.
+pub fn fish_literal(value: &str) -> String {
+    format!("'{}'", escape(value, &['\\', '\'']))
+}
+
+pub const GENERATED_HEADER: &str = "Generated by owl from setup files; do not edit";
+
+/// Write a generated file, skipping the write when nothing changed.
+pub fn write_generated(path: &Path, contents: &str) -> Result<(), String> {
+    if std::fs::read_to_string(path).ok().as_deref() == Some(contents) {
+        return Ok(());
+    }
+    write_file(path, contents)
+}
+
+/// Write a generated file even when it is unchanged, e.g. to refresh its mtime.
+pub fn write_file(path: &Path, contents: &str) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("create {}: {}", parent.display(), e))?;
+    }
+    std::fs::write(path, contents).map_err(|e| format!("write {}: {}", path.display(), e))
+}
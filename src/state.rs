@@ -0,0 +1,214 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{get_owl_state_path, LinkMode};
+
+// =======================================
+//            State Manifest
+// =======================================
+//
+// Everything owl creates on disk is recorded here so it can later be
+// inspected, undone, or pruned when the nest graph stops declaring it.
+
+fn get_state_file_path() -> PathBuf {
+    get_owl_state_path().join("state.json")
+}
+
+pub fn content_hash(contents: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(contents))
+}
+
+pub fn file_hash(path: &Path) -> Option<String> {
+    std::fs::read(path).ok().map(|c| content_hash(&c))
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn format_age(timestamp: u64) -> String {
+    let secs = now_secs().saturating_sub(timestamp);
+    match secs {
+        0..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+/// A link owl created and therefore owns.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct LinkRecord {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub setup: String,
+    #[serde(default)]
+    pub root: bool,
+    #[serde(default)]
+    pub mode: LinkMode,
+    /// Hash of the file owl wrote, for targets that are files rather than symlinks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+}
+
+/// A file owl moved out of the way before linking over it.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct BackupRecord {
+    pub target: PathBuf,
+    pub backup: PathBuf,
+    /// Seconds since the Unix epoch
+    pub created_at: u64,
+    #[serde(default)]
+    pub root: bool,
+}
+
+/// The last successful run of a setup's install script.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct InstallRecord {
+    pub setup: String,
+    pub script: PathBuf,
+    pub script_hash: String,
+    /// Seconds since the Unix epoch
+    pub ran_at: u64,
+    pub exit_code: i32,
+}
+
+/// A systemd unit owl enabled or started.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct UnitRecord {
+    /// Full unit name, e.g. `foo@bar.service` for a template instance
+    pub unit: String,
+    /// The linked unit file
+    pub file: PathBuf,
+    pub setup: String,
+    #[serde(default)]
+    pub root: bool,
+    /// Hash of the unit file when the unit was last started
+    pub unit_hash: String,
+    pub enabled: bool,
+    pub started: bool,
+}
+
+/// Serializes read-modify-write cycles from concurrently running installs.
+static UPDATE_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Deserialize, Serialize, Default)]
+pub struct OwlState {
+    #[serde(default)]
+    pub links: Vec<LinkRecord>,
+    #[serde(default)]
+    pub backups: Vec<BackupRecord>,
+    #[serde(default)]
+    pub installs: Vec<InstallRecord>,
+    #[serde(default)]
+    pub units: Vec<UnitRecord>,
+}
+
+impl OwlState {
+    pub fn load() -> Result<Self, String> {
+        let path = get_state_file_path();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let raw = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&raw).map_err(|e| format!("Invalid JSON in {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = get_state_file_path();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Unable to create {}: {}", parent.display(), e))?;
+        }
+        let raw = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Unable to serialize state: {}", e))?;
+        // Write to a sibling file first so a crash never leaves a truncated manifest
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, raw)
+            .map_err(|e| format!("Unable to write {}: {}", tmp_path.display(), e))?;
+        std::fs::rename(&tmp_path, &path)
+            .map_err(|e| format!("Unable to write {}: {}", path.display(), e))
+    }
+
+    /// Load the state, apply `f` and save it again without racing other threads.
+    pub fn update(f: impl FnOnce(&mut OwlState)) -> Result<(), String> {
+        let _guard = UPDATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut state = Self::load()?;
+        f(&mut state);
+        state.save()
+    }
+
+    /// Record a link, replacing any previous record for the same target.
+    pub fn record_link(&mut self, record: LinkRecord) {
+        self.forget_link(&record.target);
+        self.links.push(record);
+    }
+
+    pub fn forget_link(&mut self, target: &Path) {
+        self.links.retain(|l| l.target != target);
+    }
+
+    pub fn find_link(&self, target: &Path) -> Option<&LinkRecord> {
+        self.links.iter().find(|l| l.target == target)
+    }
+
+    /// True when `target` is a file owl wrote and nobody has edited since.
+    pub fn owns_unmodified_file(&self, target: &Path) -> bool {
+        match self.find_link(target).and_then(|l| l.content_hash.as_ref()) {
+            Some(hash) => file_hash(target).as_ref() == Some(hash),
+            None => false,
+        }
+    }
+
+    pub fn links_for_setup(&self, setup: &str) -> Vec<LinkRecord> {
+        self.links
+            .iter()
+            .filter(|l| l.setup == setup)
+            .cloned()
+            .collect()
+    }
+
+    pub fn record_backup(&mut self, record: BackupRecord) {
+        self.backups.push(record);
+    }
+
+    /// The most recent backup taken of `target`, if any.
+    pub fn latest_backup(&self, target: &Path) -> Option<&BackupRecord> {
+        self.backups
+            .iter()
+            .filter(|b| b.target == target)
+            .max_by_key(|b| b.created_at)
+    }
+
+    pub fn forget_backup(&mut self, backup: &Path) {
+        self.backups.retain(|b| b.backup != backup);
+    }
+
+    /// Record a successful install run, replacing any previous record for the setup.
+    pub fn record_install(&mut self, record: InstallRecord) {
+        self.installs.retain(|i| i.setup != record.setup);
+        self.installs.push(record);
+    }
+
+    pub fn find_install(&self, setup: &str) -> Option<&InstallRecord> {
+        self.installs.iter().find(|i| i.setup == setup)
+    }
+
+    /// Record a unit, replacing any previous record for the same unit and scope.
+    pub fn record_unit(&mut self, record: UnitRecord) {
+        self.units
+            .retain(|u| !(u.unit == record.unit && u.root == record.root));
+        self.units.push(record);
+    }
+
+    pub fn find_unit(&self, unit: &str, root: bool) -> Option<&UnitRecord> {
+        self.units.iter().find(|u| u.unit == unit && u.root == root)
+    }
+}
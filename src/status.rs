@@ -0,0 +1,399 @@
+use colored::{ColoredString, Colorize};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::secrets::ValidatedSecret;
+use crate::state::{self, OwlState, UnitRecord};
+use crate::{is_same_file, print_subsection, Linkable, ServiceScope, Setup, ValidatedSetupService};
+
+// =======================================
+//            Status
+// =======================================
+//
+// Compares what a setup declares against what is actually on disk, without
+// changing anything.
+
+enum LinkStatus {
+    Ok,
+    Missing,
+    PointsElsewhere(PathBuf),
+    BlockedByFile,
+    BlockedByDir,
+    SourceMissing,
+    /// Written file no longer matches what the source (and variables) produce
+    Stale,
+    /// Written file was edited after owl wrote it
+    LocallyModified,
+    /// Hardlink target is a separate file now (e.g. the source was replaced)
+    HardlinkBroken,
+    /// The contents owl would write could not be produced: the source is
+    /// unreadable or, for templates, does not render
+    RenderFailed(String),
+}
+
+impl LinkStatus {
+    fn check<T: Linkable>(item: &T, state: &OwlState) -> Self {
+        let source_path = item.source_path();
+        let target_path = item.target_path();
+
+        if !source_path.exists() {
+            return LinkStatus::SourceMissing;
+        }
+        // Everything but plain symlinks leaves a regular file at the target
+        let expects_file = item.mode_label().is_some();
+        let meta = match std::fs::symlink_metadata(&target_path) {
+            Ok(meta) => meta,
+            Err(_) => return LinkStatus::Missing,
+        };
+
+        if meta.file_type().is_symlink() {
+            return match std::fs::read_link(&target_path) {
+                Ok(dest) if dest == source_path && !expects_file => LinkStatus::Ok,
+                Ok(dest) => LinkStatus::PointsElsewhere(dest),
+                Err(_) => LinkStatus::Missing,
+            };
+        }
+        if meta.is_dir() {
+            let is_empty = std::fs::read_dir(&target_path)
+                .map(|mut it| it.next().is_none())
+                .unwrap_or(false);
+            return if is_empty {
+                LinkStatus::Missing
+            } else {
+                LinkStatus::BlockedByDir
+            };
+        }
+        if !expects_file {
+            return LinkStatus::BlockedByFile;
+        }
+
+        let recorded = state.find_link(&target_path);
+        match item.expected_contents() {
+            Some(expected) => Self::check_written(expected, &target_path, state),
+            None if is_same_file(&target_path, &source_path) => LinkStatus::Ok,
+            None if recorded.is_some() => LinkStatus::HardlinkBroken,
+            None => LinkStatus::BlockedByFile,
+        }
+    }
+
+    fn check_written(
+        expected: Result<Vec<u8>, String>,
+        target_path: &Path,
+        state: &OwlState,
+    ) -> Self {
+        let expected = match expected {
+            Ok(contents) => state::content_hash(&contents),
+            Err(e) => return LinkStatus::RenderFailed(e),
+        };
+        let current = state::file_hash(target_path);
+        if current.as_ref() == Some(&expected) {
+            return LinkStatus::Ok;
+        }
+        let recorded = state
+            .find_link(target_path)
+            .and_then(|l| l.content_hash.clone());
+        match recorded {
+            Some(recorded) if current.as_ref() == Some(&recorded) => LinkStatus::Stale,
+            Some(_) => LinkStatus::LocallyModified,
+            None => LinkStatus::BlockedByFile,
+        }
+    }
+
+    fn is_ok(&self) -> bool {
+        matches!(self, LinkStatus::Ok)
+    }
+
+    fn describe(&self) -> ColoredString {
+        match self {
+            LinkStatus::Ok => "ok".green(),
+            LinkStatus::Missing => "missing".yellow(),
+            LinkStatus::PointsElsewhere(dest) => {
+                format!("points elsewhere: {}", dest.display()).yellow()
+            }
+            LinkStatus::BlockedByFile => "blocked by regular file".red(),
+            LinkStatus::BlockedByDir => "blocked by non-empty directory".red(),
+            LinkStatus::SourceMissing => "source missing".red(),
+            LinkStatus::Stale => "stale (source changed, re-link to update)".yellow(),
+            LinkStatus::LocallyModified => "modified since owl wrote it".red(),
+            LinkStatus::HardlinkBroken => "no longer hardlinked to source".yellow(),
+            LinkStatus::RenderFailed(e) => format!("cannot render source: {}", e).red(),
+        }
+    }
+
+    /// Stable name for `--json` output.
+    fn code(&self) -> &'static str {
+        match self {
+            LinkStatus::Ok => "ok",
+            LinkStatus::Missing => "missing",
+            LinkStatus::PointsElsewhere(_) => "points_elsewhere",
+            LinkStatus::BlockedByFile => "blocked_by_file",
+            LinkStatus::BlockedByDir => "blocked_by_dir",
+            LinkStatus::SourceMissing => "source_missing",
+            LinkStatus::Stale => "stale",
+            LinkStatus::LocallyModified => "locally_modified",
+            LinkStatus::HardlinkBroken => "hardlink_broken",
+            LinkStatus::RenderFailed(_) => "render_failed",
+        }
+    }
+
+    fn detail(&self) -> Option<String> {
+        match self {
+            LinkStatus::PointsElsewhere(dest) => Some(dest.display().to_string()),
+            LinkStatus::RenderFailed(e) => Some(e.clone()),
+            _ => None,
+        }
+    }
+}
+
+struct ServiceStatus {
+    enabled: String,
+    active: String,
+}
+
+impl ServiceStatus {
+    fn check(svc: &ValidatedSetupService, unit: &str) -> Self {
+        Self {
+            enabled: systemctl_query(svc.scope, "is-enabled", unit),
+            active: systemctl_query(svc.scope, "is-active", unit),
+        }
+    }
+
+    /// Only what the service asks for is checked: a unit that is started but not
+    /// enabled may be disabled, one that is enabled but not started may be inactive.
+    fn is_ok(&self, svc: &ValidatedSetupService) -> bool {
+        let enabled_ok = !svc.enable
+            || matches!(
+                self.enabled.as_str(),
+                "enabled" | "enabled-runtime" | "static" | "indirect" | "alias" | "generated"
+            );
+        // Units without an [Install] section (e.g. timer-activated oneshots) are
+        // "static" and legitimately inactive between runs
+        let active_ok = !svc.start
+            || match self.active.as_str() {
+                "active" | "activating" | "reloading" => true,
+                "inactive" => self.enabled == "static",
+                _ => false,
+            };
+        enabled_ok && active_ok
+    }
+}
+
+fn systemctl_query(scope: ServiceScope, query: &str, name: &str) -> String {
+    let mut cmd = Command::new("systemctl");
+    if !scope.is_root() {
+        cmd.arg("--user");
+    }
+    match cmd.arg(query).arg(name).output() {
+        Ok(o) => {
+            let out = String::from_utf8_lossy(&o.stdout).trim().to_string();
+            if out.is_empty() {
+                "unknown".to_string()
+            } else {
+                out
+            }
+        }
+        Err(_) => "unknown".to_string(),
+    }
+}
+
+#[derive(Default)]
+pub struct StatusCounts {
+    pub ok: usize,
+    pub drifted: usize,
+}
+
+impl StatusCounts {
+    fn add(&mut self, ok: bool) {
+        if ok {
+            self.ok += 1;
+        } else {
+            self.drifted += 1;
+        }
+    }
+}
+
+/// One checked item. `--json` prints these as they are; the text output lists
+/// them under a subsection per section.
+#[derive(Serialize)]
+pub struct StatusEntry {
+    /// "link", "rc_script", "menu_script", "service", "secret" or "unit"
+    pub kind: &'static str,
+    pub source: PathBuf,
+    /// Where the item lives: a path, or the unit name for units
+    pub target: String,
+    pub ok: bool,
+    pub status: &'static str,
+    pub detail: Option<String>,
+    /// Subsection the text output lists it under
+    #[serde(skip)]
+    section: &'static str,
+    /// Arrow from source to target in the text output; units only show their name
+    #[serde(skip)]
+    arrow: Option<&'static str>,
+    /// The status as the text output shows it
+    #[serde(skip)]
+    message: String,
+}
+
+fn linkables_entries<T: Linkable>(
+    kind: &'static str,
+    items: &[T],
+    state: &OwlState,
+) -> Vec<StatusEntry> {
+    items
+        .iter()
+        .map(|item| {
+            let status = LinkStatus::check(item, state);
+            StatusEntry {
+                kind,
+                source: item.source_path(),
+                target: item.target_path().display().to_string(),
+                ok: status.is_ok(),
+                status: status.code(),
+                detail: status.detail(),
+                section: T::display_info(),
+                arrow: Some(item.arrow()),
+                message: status.describe().to_string(),
+            }
+        })
+        .collect()
+}
+
+fn secret_entries(secrets: &[ValidatedSecret]) -> Vec<StatusEntry> {
+    secrets
+        .iter()
+        .map(|secret| {
+            let (status, detail, message) = match secret.output_mode() {
+                None => ("missing", None, "missing, run `owl nest secrets`".red()),
+                Some(0o600) => ("ok", None, "ok".green()),
+                Some(mode) => (
+                    "wrong_mode",
+                    Some(format!("{:o}", mode)),
+                    format!("mode {:o}, expected 600", mode).yellow(),
+                ),
+            };
+            StatusEntry {
+                kind: "secret",
+                source: secret.template.clone(),
+                target: secret.output.display().to_string(),
+                ok: status == "ok",
+                status,
+                detail,
+                section: "Secrets",
+                arrow: Some("⇒"),
+                message: message.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Whether each activated unit is enabled and active as asked. Units owl only
+/// links are covered by the service link entries.
+fn unit_entries(services: &[ValidatedSetupService]) -> Vec<StatusEntry> {
+    let mut entries = Vec::new();
+    for svc in services.iter().filter(|s| s.is_activated()) {
+        let scope = if svc.scope.is_root() {
+            "system"
+        } else {
+            "user"
+        };
+        for unit in svc.units() {
+            let status = ServiceStatus::check(svc, &unit);
+            let ok = status.is_ok(svc);
+            entries.push(StatusEntry {
+                kind: "unit",
+                source: svc.target_path(),
+                target: unit,
+                ok,
+                status: if ok { "ok" } else { "drifted" },
+                detail: Some(format!("{}, {}", status.enabled, status.active)),
+                section: "Services (state)",
+                arrow: None,
+                message: format!(
+                    "({}) {}, {}",
+                    scope,
+                    status.enabled.yellow(),
+                    status.active.yellow()
+                ),
+            });
+        }
+    }
+    entries
+}
+
+/// Check everything `setup` declares against the system.
+pub fn setup_status_entries(
+    setup: &Setup,
+    skip_menu_scripts: bool,
+    state: &OwlState,
+) -> Vec<StatusEntry> {
+    let mut entries = secret_entries(&setup.secrets);
+    entries.extend(linkables_entries("link", &setup.links, state));
+    entries.extend(linkables_entries("rc_script", &setup.rc_scripts, state));
+    if !skip_menu_scripts {
+        entries.extend(linkables_entries("menu_script", &setup.menu_scripts, state));
+    }
+    entries.extend(linkables_entries("service", &setup.services, state));
+    entries.extend(unit_entries(&setup.services));
+    entries
+}
+
+fn print_entries(entries: &[StatusEntry], counts: &mut StatusCounts) {
+    let mut section = None;
+    for entry in entries {
+        if section != Some(entry.section) {
+            print_subsection(entry.section);
+            section = Some(entry.section);
+        }
+        let icon = if entry.ok { "✅" } else { "❌" };
+        match entry.arrow {
+            Some(arrow) => println!(
+                "    {} {} {} {} {}",
+                icon,
+                entry.source.display().to_string().blue(),
+                arrow,
+                entry.target.green(),
+                entry.message
+            ),
+            None => println!("    {} {} {}", icon, entry.target.green(), entry.message),
+        }
+        counts.add(entry.ok);
+    }
+}
+
+/// Print the on-disk state of everything `setup` declares and tally drift.
+pub fn print_setup_status(
+    setup: &Setup,
+    skip_menu_scripts: bool,
+    state: &OwlState,
+    counts: &mut StatusCounts,
+) {
+    print_entries(
+        &setup_status_entries(setup, skip_menu_scripts, state),
+        counts,
+    );
+}
+
+pub fn print_services_status(services: &[ValidatedSetupService], counts: &mut StatusCounts) {
+    print_entries(&unit_entries(services), counts);
+}
+
+/// List units owl enabled or started that nothing declares anymore; each counts as drift.
+pub fn print_orphaned_units(units: &[UnitRecord], counts: &mut StatusCounts) {
+    if units.is_empty() {
+        return;
+    }
+    print_subsection("Orphaned Units");
+    for unit in units {
+        let scope = if unit.root { "system" } else { "user" };
+        println!(
+            "    ❌ {} ({}) {} {}",
+            unit.unit.green(),
+            scope,
+            format!("from {}", unit.setup).cyan(),
+            "no longer declared, run `owl nest systemd --prune`".red()
+        );
+        counts.add(false);
+    }
+}
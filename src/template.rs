@@ -0,0 +1,108 @@
+use once_cell::sync::OnceCell;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command;
+
+use crate::{get_config, setup_file, SetupFileRaw};
+
+// =======================================
+//            Templates
+// =======================================
+//
+// Template links are rendered instead of symlinked. Sources use `{{ name }}`
+// placeholders, resolved from (lowest to highest precedence) the built-in
+// machine variables, the setup's `vars`, and the active nest's `vars`.
+
+pub type TemplateVars = BTreeMap<String, String>;
+
+pub fn get_hostname() -> String {
+    if let Ok(name) = std::fs::read_to_string("/proc/sys/kernel/hostname") {
+        return name.trim().to_string();
+    }
+    Command::new("hostname")
+        .output()
+        .ok()
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .unwrap_or_default()
+}
+
+fn get_user() -> String {
+    std::env::var("USER")
+        .ok()
+        .or_else(|| {
+            Command::new("whoami")
+                .output()
+                .ok()
+                .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        })
+        .unwrap_or_default()
+}
+
+fn active_nest_name() -> String {
+    get_config()
+        .nest_path
+        .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+        .unwrap_or_default()
+}
+
+static NEST_VARS: OnceCell<TemplateVars> = OnceCell::new();
+
+/// `vars` declared by the active nest; these apply to every setup it pulls in.
+fn active_nest_vars() -> &'static TemplateVars {
+    NEST_VARS.get_or_init(|| {
+        get_config()
+            .nest_path
+            .and_then(|p| setup_file::find(&p))
+            .and_then(|p| setup_file::read::<SetupFileRaw>(&p).ok())
+            .and_then(|raw| raw.vars)
+            .unwrap_or_default()
+    })
+}
+
+/// Build the full variable set for templates declared by `setup_name`.
+pub fn make_vars(setup_name: &str, setup_vars: Option<&TemplateVars>) -> TemplateVars {
+    let mut vars = TemplateVars::new();
+    vars.insert("nest".to_string(), active_nest_name());
+    vars.insert("hostname".to_string(), get_hostname());
+    vars.insert("user".to_string(), get_user());
+    vars.insert("home".to_string(), crate::tilde_expand("~"));
+    vars.insert("setup".to_string(), setup_name.to_string());
+    if let Some(setup_vars) = setup_vars {
+        vars.extend(setup_vars.clone());
+    }
+    vars.extend(active_nest_vars().clone());
+    vars
+}
+
+/// Replace every `{{ name }}` in `input`. Unknown names are an error so typos
+/// never silently render as empty strings.
+pub fn render(input: &str, vars: &TemplateVars) -> Result<String, String> {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after_open = &rest[start + 2..];
+        let end = match after_open.find("}}") {
+            Some(end) => end,
+            None => {
+                let offset = input.len() - rest.len() + start;
+                let line = input[..offset].matches('\n').count() + 1;
+                return Err(format!("unclosed '{{{{' on line {}", line));
+            }
+        };
+        let name = after_open[..end].trim();
+        match vars.get(name) {
+            Some(value) => output.push_str(value),
+            None => return Err(format!("unknown template variable '{}'", name)),
+        }
+        rest = &after_open[end + 2..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+pub fn render_file(path: &Path, vars: &TemplateVars) -> Result<String, String> {
+    let input = std::fs::read_to_string(path)
+        .map_err(|e| format!("read template {}: {}", path.display(), e))?;
+    render(&input, vars).map_err(|e| format!("render {}: {}", path.display(), e))
+}
@@ -0,0 +1,184 @@
+use once_cell::sync::OnceCell;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::template::get_hostname;
+
+// =======================================
+//            When Predicates
+// =======================================
+//
+// Any setup entry can carry a `when` clause. Every key that is present must
+// match (keys given a list match if any value does), and `not` inverts a
+// nested clause. Entries whose clause does not match are skipped on this
+// machine.
+
+#[derive(Debug, Deserialize, Clone, JsonSchema)]
+#[serde(untagged)]
+pub enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    fn values(&self) -> Vec<&str> {
+        match self {
+            OneOrMany::One(v) => vec![v.as_str()],
+            OneOrMany::Many(vs) => vs.iter().map(|v| v.as_str()).collect(),
+        }
+    }
+
+    fn any(&self, f: impl Fn(&str) -> bool) -> bool {
+        self.values().into_iter().any(f)
+    }
+
+    fn describe(&self) -> String {
+        self.values().join("|")
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct When {
+    /// Machine hostname
+    hostname: Option<OneOrMany>,
+    /// Distro `ID`/`ID_LIKE` from /etc/os-release, or `macos`
+    os: Option<OneOrMany>,
+    /// `$XDG_SESSION_TYPE` (e.g. `wayland`, `x11`)
+    session: Option<OneOrMany>,
+    /// Running window manager/desktop (e.g. `sway`, `i3`, `gnome`)
+    wm: Option<OneOrMany>,
+    /// Environment variable that must be set and non-empty
+    env: Option<OneOrMany>,
+    /// Executable that must be on `$PATH`
+    command: Option<OneOrMany>,
+    not: Option<Box<When>>,
+}
+
+struct Facts {
+    hostname: String,
+    os_ids: Vec<String>,
+    session: String,
+    wm: Vec<String>,
+}
+
+fn read_os_ids() -> Vec<String> {
+    if std::env::consts::OS == "macos" {
+        return vec!["macos".to_string()];
+    }
+    let raw = std::fs::read_to_string("/etc/os-release").unwrap_or_default();
+    let mut ids = Vec::new();
+    for line in raw.lines() {
+        let value = match line.split_once('=') {
+            Some(("ID", v)) | Some(("ID_LIKE", v)) => v,
+            _ => continue,
+        };
+        let value = value.trim_matches('"');
+        ids.extend(value.split_whitespace().map(|s| s.to_lowercase()));
+    }
+    ids
+}
+
+fn detect_wm() -> Vec<String> {
+    let mut wm = Vec::new();
+    if std::env::var_os("SWAYSOCK").is_some() {
+        wm.push("sway".to_string());
+    }
+    if std::env::var_os("I3SOCK").is_some() {
+        wm.push("i3".to_string());
+    }
+    // XDG_CURRENT_DESKTOP is a colon-separated list, e.g. "ubuntu:GNOME"
+    if let Ok(desktop) = std::env::var("XDG_CURRENT_DESKTOP") {
+        wm.extend(desktop.split(':').map(|d| d.to_lowercase()));
+    }
+    wm
+}
+
+static FACTS: OnceCell<Facts> = OnceCell::new();
+
+fn facts() -> &'static Facts {
+    FACTS.get_or_init(|| Facts {
+        hostname: get_hostname(),
+        os_ids: read_os_ids(),
+        session: std::env::var("XDG_SESSION_TYPE")
+            .unwrap_or_default()
+            .to_lowercase(),
+        wm: detect_wm(),
+    })
+}
+
+/// Distro `ID`/`ID_LIKE` values (or `macos`) for this machine.
+pub fn os_ids() -> &'static [String] {
+    &facts().os_ids
+}
+
+fn env_is_set(name: &str) -> bool {
+    std::env::var_os(name).is_some_and(|v| !v.is_empty())
+}
+
+fn command_on_path(name: &str) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    let Some(path) = std::env::var_os("PATH") else {
+        return false;
+    };
+    std::env::split_paths(&path).any(|dir| {
+        let candidate = Path::new(&dir).join(name);
+        candidate
+            .metadata()
+            .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    })
+}
+
+impl When {
+    pub fn matches(&self) -> bool {
+        let facts = facts();
+        let checks = [
+            self.hostname
+                .as_ref()
+                .map(|v| v.any(|h| h.eq_ignore_ascii_case(&facts.hostname))),
+            self.os
+                .as_ref()
+                .map(|v| v.any(|o| facts.os_ids.contains(&o.to_lowercase()))),
+            self.session
+                .as_ref()
+                .map(|v| v.any(|s| s.to_lowercase() == facts.session)),
+            self.wm
+                .as_ref()
+                .map(|v| v.any(|w| facts.wm.contains(&w.to_lowercase()))),
+            self.env.as_ref().map(|v| v.any(env_is_set)),
+            self.command.as_ref().map(|v| v.any(command_on_path)),
+            self.not.as_ref().map(|w| !w.matches()),
+        ];
+        checks.into_iter().flatten().all(|ok| ok)
+    }
+
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        let fields = [
+            ("hostname", &self.hostname),
+            ("os", &self.os),
+            ("session", &self.session),
+            ("wm", &self.wm),
+            ("env", &self.env),
+            ("command", &self.command),
+        ];
+        for (key, value) in fields {
+            if let Some(v) = value {
+                parts.push(format!("{}={}", key, v.describe()));
+            }
+        }
+        if let Some(not) = &self.not {
+            parts.push(format!("not({})", not.describe()));
+        }
+        parts.join(", ")
+    }
+}
+
+/// Raw setup entries that may carry a `when` clause.
+pub trait Conditional {
+    fn when(&self) -> Option<&When>;
+    /// Human readable summary of the entry, used when it is skipped
+    fn describe(&self) -> String;
+}